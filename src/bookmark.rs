@@ -0,0 +1,502 @@
+//! Parser for the `NETSCAPE-Bookmark-file-1` format,
+//! which is what firefox (and most other browsers) export bookmarks as,
+//! and for the json `Bookmarks` file chromium keeps in its profile directory.
+//!
+//! The netscape format is not well formed html, `<DT>` and `<p>` are never
+//! closed, so instead of a html parser we tokenize the tags and track the
+//! `<DL>` nesting to know which `<H3>` folder a link belongs to.
+
+use std::io::BufReader;
+use std::io::prelude::*;
+
+use glob::Pattern;
+use serde_json;
+use time;
+use url::Url;
+
+use text::{decode_entities, normalize_title};
+
+/// a single bookmarked link and where it was filed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bookmark {
+    pub url: Url,
+    pub title: Option<String>,
+    /// folder names from the outermost folder down to the one holding the link
+    pub folder: Vec<String>,
+    /// unix timestamp from ADD_DATE
+    pub add_date: Option<i64>,
+    /// unix timestamp from LAST_MODIFIED
+    pub last_modified: Option<i64>,
+    pub tags: Vec<String>,
+}
+
+impl Bookmark {
+    /// a link without any bookmark information, e.g. from a plain link list
+    pub fn from_url(url: Url) -> Bookmark {
+        Bookmark {
+            url,
+            title: None,
+            folder: vec![],
+            add_date: None,
+            last_modified: None,
+            tags: vec![],
+        }
+    }
+
+    pub fn folder_path(&self) -> String {
+        self.folder.join("/")
+    }
+}
+
+/// restricts which bookmarks are scraped,
+/// an empty filter lets everything through
+#[derive(Debug, Default)]
+pub struct BookmarkFilter {
+    /// folder globs, if any are given one of them has to match
+    pub include: Vec<Pattern>,
+    /// folder globs that must not match
+    pub exclude: Vec<Pattern>,
+    /// unix timestamp, inclusive
+    pub added_after: Option<i64>,
+    /// unix timestamp, exclusive
+    pub added_before: Option<i64>,
+}
+
+impl BookmarkFilter {
+    /// a pattern matches either the whole folder path
+    /// or the name of any folder along it
+    fn folder_matches(pattern: &Pattern, bookmark: &Bookmark) -> bool {
+        pattern.matches(&bookmark.folder_path())
+            || bookmark.folder.iter().any(|folder| pattern.matches(folder))
+    }
+
+    pub fn matches(&self, bookmark: &Bookmark) -> bool {
+        if !self.include.is_empty()
+            && !self.include.iter().any(|p| Self::folder_matches(p, bookmark)) {
+            return false;
+        }
+        if self.exclude.iter().any(|p| Self::folder_matches(p, bookmark)) {
+            return false;
+        }
+
+        match (self.added_after, bookmark.add_date) {
+            (Some(_), None) => return false,
+            (Some(after), Some(added)) if added < after => return false,
+            _ => {},
+        }
+        match (self.added_before, bookmark.add_date) {
+            (Some(_), None) => return false,
+            (Some(before), Some(added)) if added >= before => return false,
+            _ => {},
+        }
+
+        true
+    }
+}
+
+/// accepts either a unix timestamp or a `YYYY-MM-DD` date (UTC)
+pub fn parse_date(input: &str) -> Result<i64, String> {
+    if let Ok(timestamp) = input.parse::<i64>() {
+        return Ok(timestamp);
+    }
+    time::strptime(input, "%Y-%m-%d")
+        .map(|tm| tm.to_timespec().sec)
+        .map_err(|e| format!("invalid date '{}': {}, expected YYYY-MM-DD", input, e))
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Tag {
+    name: String, // uppercase
+    attributes: Vec<(String, String)>, // uppercase name, raw value
+}
+
+impl Tag {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.iter()
+            .find(|&(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Token<'a> {
+    Open(Tag),
+    Close(String),
+    Text(&'a str),
+}
+
+fn tokenize(input: &str) -> Vec<Token<'_>> {
+    let bytes = input.as_bytes();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+
+    while pos < bytes.len() {
+        if bytes[pos] != b'<' {
+            let end = input[pos..].find('<').map_or(input.len(), |i| pos + i);
+            tokens.push(Token::Text(&input[pos..end]));
+            pos = end;
+            continue;
+        }
+
+        pos += 1;
+        // doctype and comments carry no information
+        if input[pos..].starts_with('!') {
+            pos = input[pos..].find('>').map_or(input.len(), |i| pos + i + 1);
+            continue;
+        }
+
+        let closing = input[pos..].starts_with('/');
+        if closing {
+            pos += 1;
+        }
+        let name_end = input[pos..].find(|c: char| !c.is_ascii_alphanumeric())
+            .map_or(input.len(), |i| pos + i);
+        let name = input[pos..name_end].to_ascii_uppercase();
+        pos = name_end;
+
+        let mut attributes = Vec::new();
+        loop {
+            while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+            if pos >= bytes.len() {
+                break;
+            }
+            if bytes[pos] == b'>' {
+                pos += 1;
+                break;
+            }
+
+            let key_end = input[pos..].find(|c: char| c == '=' || c == '>' || c.is_whitespace())
+                .map_or(input.len(), |i| pos + i);
+            let key = input[pos..key_end].to_ascii_uppercase();
+            pos = key_end;
+            if key.is_empty() { // stray character, e.g. a self closing '/'
+                pos += input[pos..].chars().next().map_or(1, |c| c.len_utf8());
+                continue;
+            }
+
+            let mut value = String::new();
+            if pos < bytes.len() && bytes[pos] == b'=' {
+                pos += 1;
+                let value_end = match bytes.get(pos) {
+                    Some(&quote) if quote == b'"' || quote == b'\'' => {
+                        pos += 1;
+                        let end = input[pos..].find(quote as char).map_or(input.len(), |i| pos + i);
+                        value = input[pos..end].to_string();
+                        (end + 1).min(input.len())
+                    },
+                    _ => {
+                        let end = input[pos..].find(|c: char| c == '>' || c.is_whitespace())
+                            .map_or(input.len(), |i| pos + i);
+                        value = input[pos..end].to_string();
+                        end
+                    },
+                };
+                pos = value_end;
+            }
+            attributes.push((key, value));
+        }
+
+        if closing {
+            tokens.push(Token::Close(name));
+        } else {
+            tokens.push(Token::Open(Tag { name, attributes }));
+        }
+    }
+
+    tokens
+}
+
+/// concatenated text up to the closing tag `name`, e.g. the title of a link
+fn text_until<'a, I>(tokens: &mut I, name: &str) -> String
+    where I: Iterator<Item = Token<'a>>
+{
+    let mut text = String::new();
+    for token in tokens {
+        match token {
+            Token::Text(t) => text.push_str(t),
+            Token::Close(ref close) if close == name => break,
+            _ => continue,
+        }
+    }
+    normalize_title(&text)
+}
+
+fn timestamp(tag: &Tag, name: &str) -> Option<i64> {
+    tag.attribute(name).and_then(|t| t.trim().parse().ok())
+}
+
+/// links that are not valid urls are skipped
+pub fn parse_bookmarks(input: &str) -> Vec<Bookmark> {
+    let mut bookmarks = Vec::new();
+    // one entry per open <DL>, None for lists that are not a named folder
+    let mut folders: Vec<Option<String>> = Vec::new();
+    let mut pending_folder: Option<String> = None;
+
+    let mut tokens = tokenize(input).into_iter();
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::Open(ref tag) if tag.name == "H3" => {
+                pending_folder = Some(text_until(&mut tokens, "H3"));
+            },
+            Token::Open(ref tag) if tag.name == "DL" => {
+                folders.push(pending_folder.take());
+            },
+            Token::Close(ref name) if name == "DL" => {
+                folders.pop();
+            },
+            Token::Open(ref tag) if tag.name == "A" => {
+                let title = text_until(&mut tokens, "A");
+                let url = match tag.attribute("HREF").map(|href| Url::parse(&decode_entities(href))) {
+                    Some(Ok(url)) => url,
+                    _ => continue,
+                };
+                let tags = tag.attribute("TAGS")
+                    .map(|tags| tags.split(',')
+                         .map(normalize_title)
+                         .filter(|t| !t.is_empty())
+                         .collect())
+                    .unwrap_or_default();
+
+                bookmarks.push(Bookmark {
+                    url,
+                    title: if title.is_empty() { None } else { Some(title) },
+                    folder: folders.iter().filter_map(|f| f.clone()).collect(),
+                    add_date: timestamp(tag, "ADD_DATE"),
+                    last_modified: timestamp(tag, "LAST_MODIFIED"),
+                    tags,
+                });
+            },
+            _ => continue,
+        }
+    }
+
+    bookmarks
+}
+
+/// a node in chromium's bookmark tree, either a "folder" or a "url"
+#[derive(Debug, Deserialize)]
+struct ChromiumNode {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    name: String,
+    url: Option<String>,
+    date_added: Option<String>,
+    date_modified: Option<String>,
+    #[serde(default)]
+    children: Vec<ChromiumNode>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChromiumBookmarks {
+    // NB(nils): older versions also keep non-node values in here,
+    // NB(nils): e.g. "sync_transaction_version"
+    roots: serde_json::Map<String, serde_json::Value>,
+}
+
+/// chromium stores microseconds since 1601-01-01 as a string, 0 meaning unset
+fn chromium_timestamp(webkit: &Option<String>) -> Option<i64> {
+    const WEBKIT_TO_UNIX_EPOCH: i64 = 11_644_473_600;
+    webkit.as_ref()
+        .and_then(|t| t.parse::<i64>().ok())
+        .filter(|&t| t > 0)
+        .map(|t| t / 1_000_000 - WEBKIT_TO_UNIX_EPOCH)
+}
+
+fn collect_chromium(node: &ChromiumNode, folder: &mut Vec<String>, bookmarks: &mut Vec<Bookmark>) {
+    match node.kind.as_str() {
+        "folder" => {
+            folder.push(node.name.clone());
+            for child in &node.children {
+                collect_chromium(child, folder, bookmarks);
+            }
+            folder.pop();
+        },
+        "url" => {
+            let url = match node.url.as_ref().map(|url| Url::parse(url)) {
+                Some(Ok(url)) => url,
+                _ => return,
+            };
+            bookmarks.push(Bookmark {
+                url,
+                title: Some(normalize_title(&node.name)).filter(|title| !title.is_empty()),
+                folder: folder.clone(),
+                add_date: chromium_timestamp(&node.date_added),
+                last_modified: chromium_timestamp(&node.date_modified),
+                tags: vec![],
+            });
+        },
+        _ => {},
+    }
+}
+
+/// the root folders ("Bookmarks bar", "Other bookmarks", ...) are kept
+/// as the outermost folder, like firefox exports its toolbar
+pub fn parse_chromium_bookmarks(input: &str) -> Result<Vec<Bookmark>, serde_json::Error> {
+    let file: ChromiumBookmarks = serde_json::from_str(input)?;
+
+    let mut bookmarks = Vec::new();
+    for root in file.roots.values() {
+        if let Ok(node) = serde_json::from_value::<ChromiumNode>(root.clone()) {
+            collect_chromium(&node, &mut Vec::new(), &mut bookmarks);
+        }
+    }
+    Ok(bookmarks)
+}
+
+pub fn read_bookmarks<R: Read>(reader: R) -> Vec<Bookmark> {
+    let mut content = String::new();
+    match BufReader::new(reader).read_to_string(&mut content) {
+        Ok(_) => parse_bookmarks(&content),
+        Err(e) => {
+            println!("could not read bookmark file {}", e);
+            Vec::new()
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs::File;
+
+    #[test]
+    fn test_parse_bookmark_entry() {
+        let mut file = File::open("test_resources/bookmark_entry.txt").expect("could not open entry");
+        let mut entry = String::new();
+        file.read_to_string(&mut entry).expect("could not read entry");
+
+        let expected = Bookmark {
+            url: Url::parse("https://www.reddit.com/r/Metal/comments/3quxqv/black_zuriaake_%E6%A2%A6%E9%82%80_2015_china_ffo_actual_chinese/").unwrap(),
+            title: Some(String::from("[Black] Zuriaake - 梦邀 (2015, China, FFO: actual Chinese BM, Paysage d'Hiver, Lunar Aurora) : Metal")),
+            folder: vec![],
+            add_date: Some(1447956450),
+            last_modified: Some(1447956450),
+            tags: vec![],
+        };
+
+        assert_eq!(parse_bookmarks(&entry), vec![expected]);
+    }
+
+    #[test]
+    fn test_bookmark_folders() {
+        let input = "<!DOCTYPE NETSCAPE-Bookmark-file-1>\n\
+            <DL><p>\n\
+                <DT><A HREF=\"http://top.example/\">top</A>\n\
+                <DT><H3 ADD_DATE=\"1\">outer</H3>\n\
+                <DL><p>\n\
+                    <DT><H3>inner</H3>\n\
+                    <DL><p>\n\
+                        <DT><A HREF=\"http://inner.example/?a=1&amp;b=2\" ADD_DATE=\"3\" TAGS=\"metal,doom\">inner link</A>\n\
+                    </DL><p>\n\
+                    <DT><A href='http://outer.example/'>outer link</A>\n\
+                    <DT><A HREF=\"not a url\">broken</A>\n\
+                </DL><p>\n\
+            </DL>\n";
+
+        let bookmarks = parse_bookmarks(input);
+        assert_eq!(bookmarks.len(), 3);
+
+        assert_eq!(bookmarks[0].folder_path(), "");
+        assert_eq!(bookmarks[0].add_date, None);
+
+        assert_eq!(bookmarks[1].url.as_str(), "http://inner.example/?a=1&b=2");
+        assert_eq!(bookmarks[1].folder_path(), "outer/inner");
+        assert_eq!(bookmarks[1].add_date, Some(3));
+        assert_eq!(bookmarks[1].tags, vec!["metal", "doom"]);
+
+        assert_eq!(bookmarks[2].title, Some(String::from("outer link")));
+        assert_eq!(bookmarks[2].folder, vec!["outer"]);
+    }
+
+    fn bookmark_in(folder: &[&str], add_date: Option<i64>) -> Bookmark {
+        Bookmark {
+            url: Url::parse("https://www.reddit.com/r/Metal/comments/5k0ncr/black_weakling_dead_as_dreams/").unwrap(),
+            title: None,
+            folder: folder.iter().map(|f| f.to_string()).collect(),
+            add_date,
+            last_modified: None,
+            tags: vec![],
+        }
+    }
+
+    #[test]
+    fn test_filter_folders() {
+        let filter = BookmarkFilter {
+            include: vec![Pattern::new("musik*").unwrap()],
+            exclude: vec![Pattern::new("*2015*").unwrap()],
+            ..Default::default()
+        };
+
+        assert!(filter.matches(&bookmark_in(&["musik20160217"], None)));
+        assert!(filter.matches(&bookmark_in(&["Bookmarks Toolbar", "musik20160217"], None)));
+        assert!(!filter.matches(&bookmark_in(&["musik 20151119"], None)));
+        assert!(!filter.matches(&bookmark_in(&["Mozilla Firefox"], None)));
+        assert!(!filter.matches(&bookmark_in(&[], None)));
+
+        assert!(BookmarkFilter::default().matches(&bookmark_in(&[], None)));
+    }
+
+    #[test]
+    fn test_filter_dates() {
+        let filter = BookmarkFilter {
+            added_after: Some(parse_date("2015-12-01").unwrap()),
+            added_before: Some(parse_date("2016-01-01").unwrap()),
+            ..Default::default()
+        };
+
+        assert!(filter.matches(&bookmark_in(&[], Some(1449508810)))); // 2015-12-07
+        assert!(!filter.matches(&bookmark_in(&[], Some(1447956450)))); // 2015-11-19
+        assert!(!filter.matches(&bookmark_in(&[], Some(1455740183)))); // 2016-02-17
+        assert!(!filter.matches(&bookmark_in(&[], None)));
+    }
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(parse_date("2015-11-19"), Ok(1447891200));
+        assert_eq!(parse_date("1447956450"), Ok(1447956450));
+        assert!(parse_date("last tuesday").is_err());
+    }
+
+    #[test]
+    fn test_read_chromium_bookmarks() {
+        let mut file = File::open("test_resources/chromium_bookmarks").expect("could not open bookmark");
+        let mut content = String::new();
+        file.read_to_string(&mut content).expect("could not read bookmark");
+        let bookmarks = parse_chromium_bookmarks(&content).expect("could not parse bookmark");
+
+        assert_eq!(bookmarks.len(), 3);
+        assert_eq!(bookmarks[0].url.as_str(), "https://www.reddit.com/r/Metal/comments/3quxqv/black_zuriaake_%E6%A2%A6%E9%82%80_2015_china_ffo_actual_chinese/");
+        assert_eq!(bookmarks[0].folder_path(), "Bookmarks bar/musik 20151119");
+        assert_eq!(bookmarks[0].add_date, Some(1447454850));
+        assert_eq!(bookmarks[1].folder, vec!["Bookmarks bar"]);
+        assert_eq!(bookmarks[1].title, Some(String::from("Spectral Lore - Cosmic Significance")));
+        assert_eq!(bookmarks[2].folder, vec!["Other bookmarks"]);
+    }
+
+    #[test]
+    fn test_chromium_timestamp() {
+        assert_eq!(chromium_timestamp(&Some(String::from("13091928450000000"))), Some(1447454850));
+        assert_eq!(chromium_timestamp(&Some(String::from("0"))), None);
+        assert_eq!(chromium_timestamp(&None), None);
+    }
+
+    #[test]
+    fn test_read_example_bookmarks() {
+        let file = File::open("test_resources/example_bookmark.html").expect("could not open bookmark");
+        let bookmarks = read_bookmarks(&file);
+
+        let musik = bookmarks.iter()
+            .filter(|b| b.folder_path() == "musik 20151119")
+            .collect::<Vec<_>>();
+        assert!(!musik.is_empty());
+        assert_eq!(musik[0].add_date, Some(1447956450));
+        assert!(bookmarks.iter().any(|b| b.folder_path() == "20160403-musik"));
+
+        let titles = bookmarks.iter().filter_map(|b| b.title.as_ref()).collect::<Vec<_>>();
+        assert!(titles.iter().any(|t| t.starts_with("[Death Metal] Chthe'ilist - ")));
+        assert!(titles.iter().all(|t| !t.contains("&#39;") && !t.contains("&quot;")));
+    }
+}
@@ -0,0 +1,203 @@
+//! The comment tree of a thread.
+//!
+//! Json output nests replies inside their parent, csv and sqlite get one
+//! row per comment with its depth and parent id instead.
+//!
+//! Replies often recommend similar music, the links to music sites in
+//! comment bodies are collected by `music_links`.
+
+use url::Url;
+
+use bookmark::Bookmark;
+use input;
+use text;
+use thing::{Comment, Thing};
+
+/// hosts of the music sites worth collecting from comments,
+/// subdomains match as well, e.g. "<artist>.bandcamp.com"
+const MUSIC_HOSTS: [&str; 6] = [
+    "youtube.com",
+    "youtu.be",
+    "bandcamp.com",
+    "spotify.com",
+    "soundcloud.com",
+    "snd.sc",
+];
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct CommentNode {
+    pub id: Option<String>,
+    /// `t3_<id>` for top level comments, `t1_<id>` for replies
+    pub parent_id: Option<String>,
+    /// 0 for top level comments
+    pub depth: usize,
+    pub author: Option<String>,
+    pub score: Option<i64>,
+    pub created_utc: Option<i64>,
+    /// markdown, html entities decoded
+    pub body: Option<String>,
+    pub replies: Vec<CommentNode>,
+}
+
+impl CommentNode {
+    fn from_comment(comment: &Comment, depth: usize) -> CommentNode {
+        let replies = comment.replies.as_ref()
+            .map(|listing| comment_tree(&listing.children, depth + 1))
+            .unwrap_or_default();
+        CommentNode {
+            id: comment.id.clone(),
+            parent_id: comment.parent_id.clone(),
+            depth,
+            author: comment.author.clone(),
+            score: comment.score,
+            created_utc: comment.created_utc.map(|t| t as i64),
+            body: comment.body.as_ref().map(|body| text::decode_entities(body)),
+            replies,
+        }
+    }
+}
+
+/// the comments among `things`, "load more" stubs are left out
+pub fn comment_tree(things: &[Thing], depth: usize) -> Vec<CommentNode> {
+    things.iter()
+        .filter_map(|thing| match *thing {
+            Thing::Comment(ref comment) => Some(CommentNode::from_comment(comment, depth)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// every comment of the tree, parents before their replies
+pub fn flatten(tree: &[CommentNode]) -> Vec<&CommentNode> {
+    let mut comments = Vec::new();
+    for node in tree {
+        comments.push(node);
+        comments.extend(flatten(&node.replies));
+    }
+    comments
+}
+
+pub fn is_music_link(url: &Url) -> bool {
+    let host = match url.host_str() {
+        Some(host) => host.to_lowercase(),
+        None => return false,
+    };
+    MUSIC_HOSTS.iter().any(|music| host == *music || host.ends_with(&format!(".{}", music)))
+}
+
+/// music links in the comments and the comment each was first posted in,
+/// a link repeated further down the thread is only kept once
+pub fn music_links(tree: &[CommentNode]) -> Vec<(&CommentNode, Bookmark)> {
+    let mut seen = Vec::new();
+    let mut links = Vec::new();
+    for comment in flatten(tree) {
+        let body = match comment.body {
+            Some(ref body) => body,
+            None => continue,
+        };
+        for link in input::extract_links(body) {
+            if is_music_link(&link.url) && !seen.contains(&link.url) {
+                seen.push(link.url.clone());
+                links.push((comment, link));
+            }
+        }
+    }
+    links
+}
+
+/// a comment as a table row, `reddit_id` is the thread
+#[derive(Debug, Serialize)]
+pub struct CommentRow<'a> {
+    pub reddit_id: &'a str,
+    pub id: Option<&'a str>,
+    pub parent_id: Option<&'a str>,
+    pub depth: usize,
+    pub author: Option<&'a str>,
+    pub score: Option<i64>,
+    pub created_utc: Option<i64>,
+    pub body: Option<&'a str>,
+}
+
+pub fn rows<'a>(reddit_id: &'a str, tree: &'a [CommentNode]) -> Vec<CommentRow<'a>> {
+    flatten(tree).into_iter()
+        .map(|node| CommentRow {
+            reddit_id,
+            id: node.id.as_deref(),
+            parent_id: node.parent_id.as_deref(),
+            depth: node.depth,
+            author: node.author.as_deref(),
+            score: node.score,
+            created_utc: node.created_utc,
+            body: node.body.as_deref(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json;
+
+    fn tree() -> Vec<CommentNode> {
+        let json = r#"[{"kind": "t1", "data": {"id": "c1", "parent_id": "t3_abc", "author": "a",
+                         "body": "FFO &amp; more", "score": 3, "created_utc": 1482543088.0,
+                         "replies": {"kind": "Listing", "data": {"children": [
+                             {"kind": "t1", "data": {"id": "c2", "parent_id": "t1_c1", "replies": ""}},
+                             {"kind": "more", "data": {"count": 2, "children": ["c4", "c5"]}}]}}}},
+                       {"kind": "t1", "data": {"id": "c3", "parent_id": "t3_abc", "replies": ""}}]"#;
+        let things: Vec<Thing> = serde_json::from_str(json).expect("could not parse comments");
+        comment_tree(&things, 0)
+    }
+
+    #[test]
+    fn test_comment_tree() {
+        let tree = tree();
+        assert_eq!(tree.len(), 2);
+        assert_eq!(tree[0].body, Some(String::from("FFO & more")));
+        assert_eq!(tree[0].created_utc, Some(1482543088));
+        assert_eq!(tree[0].replies.len(), 1);
+        assert_eq!(tree[0].replies[0].depth, 1);
+        assert!(tree[1].replies.is_empty());
+    }
+
+    #[test]
+    fn test_music_links() {
+        let json = r#"[{"kind": "t1", "data": {"id": "c1", "body":
+                         "try [Paysage d'Hiver](https://paysagedhiver.bandcamp.com/album/im-wald) or https://youtu.be/bbvBJMDbyeo, not https://www.reddit.com/r/Metal/",
+                         "replies": {"kind": "Listing", "data": {"children": [
+                             {"kind": "t1", "data": {"id": "c2", "body":
+                               "again https://youtu.be/bbvBJMDbyeo and https://open.spotify.com/track/abc"}}]}}}}]"#;
+        let things: Vec<Thing> = serde_json::from_str(json).expect("could not parse comments");
+        let tree = comment_tree(&things, 0);
+
+        let links = music_links(&tree).into_iter()
+            .map(|(comment, link)| (comment.id.clone().unwrap(), link.url.to_string(), link.title))
+            .collect::<Vec<_>>();
+        assert_eq!(links, vec![
+            (String::from("c1"), String::from("https://paysagedhiver.bandcamp.com/album/im-wald"),
+             Some(String::from("Paysage d'Hiver"))),
+            (String::from("c1"), String::from("https://youtu.be/bbvBJMDbyeo"), None),
+            (String::from("c2"), String::from("https://open.spotify.com/track/abc"), None),
+        ]);
+    }
+
+    #[test]
+    fn test_is_music_link() {
+        let is_music = |url: &str| is_music_link(&Url::parse(url).unwrap());
+        assert!(is_music("https://www.youtube.com/watch?v=bbvBJMDbyeo"));
+        assert!(is_music("https://m.soundcloud.com/artist/track"));
+        assert!(!is_music("https://notyoutube.com/watch"));
+        assert!(!is_music("https://www.reddit.com/r/Metal/"));
+    }
+
+    #[test]
+    fn test_rows() {
+        let tree = tree();
+        let rows = rows("abc", &tree);
+        let ids = rows.iter().map(|row| (row.id, row.parent_id, row.depth)).collect::<Vec<_>>();
+        assert_eq!(ids, vec![(Some("c1"), Some("t3_abc"), 0),
+                             (Some("c2"), Some("t1_c1"), 1),
+                             (Some("c3"), Some("t3_abc"), 0)]);
+        assert!(rows.iter().all(|row| row.reddit_id == "abc"));
+    }
+}
@@ -0,0 +1,423 @@
+//! An sqlite database as output, meant to be kept between runs.
+//!
+//! Entries are keyed by their reddit id, a rerun updates votes, comment
+//! counts and titles in place instead of adding rows. The bookmarks an
+//! entry was scraped from are kept in their own table, one row per folder,
+//! and so are the comments of each thread if asked for.
+//!
+//! Music links found in comments have no reddit id of their own, they go
+//! into `comment_links` keyed by the comment and the link.
+
+use std::error::Error;
+use std::path::Path;
+
+use rusqlite::{params, Connection};
+use time;
+
+use comments;
+use output::EntryWriter;
+use RedditEntry;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS entries (
+        reddit_id TEXT PRIMARY KEY NOT NULL,
+        url TEXT,
+        title TEXT,
+        subreddit TEXT,
+        votes INTEGER,
+        comments INTEGER,
+        self_link TEXT,
+        genre TEXT,
+        artist TEXT,
+        track TEXT,
+        year INTEGER,
+        country TEXT,
+        ffo TEXT,
+        author TEXT,
+        created_utc INTEGER,
+        domain TEXT,
+        over_18 INTEGER,
+        flair TEXT,
+        gilded INTEGER,
+        upvote_ratio REAL,
+        is_self INTEGER,
+        selftext TEXT,
+        media_provider TEXT,
+        media_title TEXT,
+        media_author TEXT,
+        media_thumbnail TEXT,
+        media_html TEXT,
+        first_scraped INTEGER NOT NULL,
+        last_scraped INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS bookmarks (
+        reddit_id TEXT NOT NULL REFERENCES entries (reddit_id),
+        folder TEXT NOT NULL,
+        title TEXT,
+        added INTEGER,
+        tags TEXT,
+        PRIMARY KEY (reddit_id, folder)
+    );
+    CREATE TABLE IF NOT EXISTS comments (
+        id TEXT PRIMARY KEY NOT NULL,
+        reddit_id TEXT NOT NULL REFERENCES entries (reddit_id),
+        parent_id TEXT,
+        depth INTEGER NOT NULL,
+        author TEXT,
+        score INTEGER,
+        created_utc INTEGER,
+        body TEXT
+    );
+    CREATE TABLE IF NOT EXISTS comment_links (
+        comment_id TEXT NOT NULL,
+        url TEXT NOT NULL,
+        reddit_id TEXT NOT NULL REFERENCES entries (reddit_id),
+        title TEXT,
+        author TEXT,
+        score INTEGER,
+        genre TEXT,
+        artist TEXT,
+        track TEXT,
+        first_scraped INTEGER NOT NULL,
+        last_scraped INTEGER NOT NULL,
+        PRIMARY KEY (comment_id, url)
+    );";
+
+/// entry columns added after the first version of the schema,
+/// databases of earlier runs get them on open
+const ADDED_COLUMNS: [(&str, &str); 20] = [
+    ("genre", "TEXT"),
+    ("artist", "TEXT"),
+    ("track", "TEXT"),
+    ("year", "INTEGER"),
+    ("country", "TEXT"),
+    ("ffo", "TEXT"),
+    ("author", "TEXT"),
+    ("created_utc", "INTEGER"),
+    ("domain", "TEXT"),
+    ("over_18", "INTEGER"),
+    ("flair", "TEXT"),
+    ("gilded", "INTEGER"),
+    ("upvote_ratio", "REAL"),
+    ("is_self", "INTEGER"),
+    ("selftext", "TEXT"),
+    ("media_provider", "TEXT"),
+    ("media_title", "TEXT"),
+    ("media_author", "TEXT"),
+    ("media_thumbnail", "TEXT"),
+    ("media_html", "TEXT"),
+];
+
+fn add_missing_columns(connection: &Connection) -> rusqlite::Result<()> {
+    let existing = connection.prepare("PRAGMA table_info(entries)")?
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    for &(name, kind) in ADDED_COLUMNS.iter() {
+        if !existing.iter().any(|column| column == name) {
+            connection.execute_batch(&format!("ALTER TABLE entries ADD COLUMN {} {};", name, kind))?;
+        }
+    }
+    Ok(())
+}
+
+pub struct DatabaseOutput {
+    connection: Connection,
+    comments: bool,
+}
+
+impl DatabaseOutput {
+    /// opens or creates the database, existing entries are kept
+    pub fn open<P: AsRef<Path>>(path: P) -> rusqlite::Result<DatabaseOutput> {
+        DatabaseOutput::from_connection(Connection::open(path)?)
+    }
+
+    fn from_connection(connection: Connection) -> rusqlite::Result<DatabaseOutput> {
+        connection.execute_batch(SCHEMA)?;
+        add_missing_columns(&connection)?;
+        Ok(DatabaseOutput { connection, comments: false })
+    }
+
+    pub fn with_comments(mut self, comments: bool) -> DatabaseOutput {
+        self.comments = comments;
+        self
+    }
+
+    /// inserts the entry or updates the row of an earlier run
+    pub fn upsert(&mut self, entry: &RedditEntry, scraped: i64) -> Result<(), Box<dyn Error>> {
+        if let Some(ref thread_id) = entry.found_in {
+            return self.upsert_comment_link(thread_id, entry, scraped);
+        }
+        let reddit_id = entry.reddit_id.as_ref()
+            .ok_or_else(|| format!("entry without reddit id {:?}", entry.self_link))?;
+
+        let transaction = self.connection.transaction()?;
+        transaction.execute(
+            "INSERT INTO entries (reddit_id, url, title, subreddit, votes, comments, self_link,
+                                  genre, artist, track, year, country, ffo,
+                                  author, created_utc, domain, over_18, flair, gilded,
+                                  upvote_ratio, is_self, selftext, media_provider,
+                                  media_title, media_author, media_thumbnail, media_html,
+                                  first_scraped, last_scraped)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13,
+                     ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25, ?26, ?27,
+                     ?28, ?28)
+             ON CONFLICT (reddit_id) DO UPDATE SET
+                 url = excluded.url,
+                 title = excluded.title,
+                 subreddit = excluded.subreddit,
+                 votes = excluded.votes,
+                 comments = excluded.comments,
+                 self_link = excluded.self_link,
+                 genre = excluded.genre,
+                 artist = excluded.artist,
+                 track = excluded.track,
+                 year = excluded.year,
+                 country = excluded.country,
+                 ffo = excluded.ffo,
+                 author = excluded.author,
+                 created_utc = excluded.created_utc,
+                 domain = excluded.domain,
+                 over_18 = excluded.over_18,
+                 flair = excluded.flair,
+                 gilded = excluded.gilded,
+                 upvote_ratio = excluded.upvote_ratio,
+                 is_self = excluded.is_self,
+                 selftext = excluded.selftext,
+                 media_provider = excluded.media_provider,
+                 media_title = excluded.media_title,
+                 media_author = excluded.media_author,
+                 media_thumbnail = excluded.media_thumbnail,
+                 media_html = excluded.media_html,
+                 last_scraped = excluded.last_scraped",
+            params![reddit_id,
+                    entry.url.as_ref().map(|link| link.to_string()),
+                    entry.title,
+                    entry.subreddit,
+                    entry.votes,
+                    entry.comments.map(|comments| comments as i64),
+                    entry.self_link.as_ref().map(|link| link.to_string()),
+                    entry.genre,
+                    entry.artist,
+                    entry.track,
+                    entry.year,
+                    entry.country,
+                    entry.ffo,
+                    entry.author,
+                    entry.created_utc,
+                    entry.domain,
+                    entry.over_18,
+                    entry.flair,
+                    entry.gilded.map(|gilded| gilded as i64),
+                    entry.upvote_ratio,
+                    entry.is_self,
+                    entry.selftext,
+                    entry.media_provider,
+                    entry.media_title,
+                    entry.media_author,
+                    entry.media_thumbnail,
+                    entry.media_html,
+                    scraped])?;
+
+        if entry.bookmark_title.is_some() || entry.folder.is_some() || entry.added.is_some() {
+            // NB(nils): no folder is stored as "" since NULLs never
+            // NB(nils): conflict in a primary key
+            transaction.execute(
+                "INSERT INTO bookmarks (reddit_id, folder, title, added, tags)
+                 VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT (reddit_id, folder) DO UPDATE SET
+                     title = excluded.title,
+                     added = excluded.added,
+                     tags = excluded.tags",
+                params![reddit_id,
+                        entry.folder.as_deref().unwrap_or(""),
+                        entry.bookmark_title,
+                        entry.added,
+                        entry.tags])?;
+        }
+
+        if self.comments {
+            // NB(nils): comments are edited and voted on too, so they are updated as well
+            let mut statement = transaction.prepare(
+                "INSERT INTO comments (id, reddit_id, parent_id, depth, author, score,
+                                       created_utc, body)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+                 ON CONFLICT (id) DO UPDATE SET
+                     author = excluded.author,
+                     score = excluded.score,
+                     body = excluded.body")?;
+            for row in comments::rows(reddit_id, &entry.comment_tree) {
+                let id = match row.id {
+                    Some(id) => id,
+                    None => continue,
+                };
+                statement.execute(params![id, row.reddit_id, row.parent_id, row.depth as i64,
+                                          row.author, row.score, row.created_utc, row.body])?;
+            }
+        }
+
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn upsert_comment_link(&mut self, thread_id: &str, entry: &RedditEntry, scraped: i64)
+                           -> Result<(), Box<dyn Error>> {
+        let comment_id = entry.comment_id.as_ref()
+            .ok_or_else(|| format!("comment link without comment id in {}", thread_id))?;
+        let url = entry.url.as_ref()
+            .ok_or_else(|| format!("comment link without url in {}", comment_id))?;
+
+        self.connection.execute(
+            "INSERT INTO comment_links (comment_id, url, reddit_id, title, author, score,
+                                        genre, artist, track, first_scraped, last_scraped)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?10)
+             ON CONFLICT (comment_id, url) DO UPDATE SET
+                 title = excluded.title,
+                 score = excluded.score,
+                 genre = excluded.genre,
+                 artist = excluded.artist,
+                 track = excluded.track,
+                 last_scraped = excluded.last_scraped",
+            params![comment_id,
+                    url.to_string(),
+                    thread_id,
+                    entry.title,
+                    entry.comment_author,
+                    entry.votes,
+                    entry.genre,
+                    entry.artist,
+                    entry.track,
+                    scraped])?;
+        Ok(())
+    }
+}
+
+impl EntryWriter for DatabaseOutput {
+    fn write(&mut self, entry: &RedditEntry) -> Result<(), Box<dyn Error>> {
+        self.upsert(entry, time::get_time().sec)
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), Box<dyn Error>> {
+        self.connection.close().map_err(|(_, e)| e)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn count(database: &DatabaseOutput, table: &str) -> i64 {
+        database.connection
+            .query_row(&format!("SELECT count(*) FROM {}", table), [], |row| row.get(0))
+            .expect("could not count rows")
+    }
+
+    #[test]
+    fn test_upsert_entry() {
+        let mut database = DatabaseOutput::from_connection(Connection::open_in_memory().unwrap())
+            .expect("could not create schema");
+
+        let mut entry = RedditEntry::new();
+        database.upsert(&entry, 100).expect("could not insert entry");
+        entry.votes = Some(90);
+        entry.comments = Some(15);
+        database.upsert(&entry, 200).expect("could not update entry");
+
+        assert_eq!(count(&database, "entries"), 1);
+        let row: (i64, i64, i64, i64) = database.connection
+            .query_row("SELECT votes, comments, first_scraped, last_scraped FROM entries \
+                        WHERE reddit_id = '5k0ncr'", [],
+                       |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))
+            .unwrap();
+        assert_eq!(row, (90, 15, 100, 200));
+        let post: (String, String, f64) = database.connection
+            .query_row("SELECT artist, author, upvote_ratio FROM entries", [],
+                       |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?))).unwrap();
+        assert_eq!(post, (String::from("Weakling"), String::from("sakyamuni_lotus777"), 0.91));
+        assert_eq!(count(&database, "bookmarks"), 0);
+    }
+
+    #[test]
+    fn test_add_missing_columns() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection.execute_batch(
+            "CREATE TABLE entries (reddit_id TEXT PRIMARY KEY NOT NULL, url TEXT, title TEXT,
+                                   subreddit TEXT, votes INTEGER, comments INTEGER,
+                                   self_link TEXT, first_scraped INTEGER NOT NULL,
+                                   last_scraped INTEGER NOT NULL);").unwrap();
+        let mut database = DatabaseOutput::from_connection(connection)
+            .expect("could not update schema");
+
+        database.upsert(&RedditEntry::new(), 100).expect("could not insert entry");
+        assert_eq!(count(&database, "entries"), 1);
+    }
+
+    #[test]
+    fn test_upsert_bookmarks() {
+        let mut database = DatabaseOutput::from_connection(Connection::open_in_memory().unwrap())
+            .expect("could not create schema");
+
+        let mut entry = RedditEntry::new();
+        entry.folder = Some(String::from("musik 20151119"));
+        entry.added = Some(1447956450);
+        database.upsert(&entry, 100).unwrap();
+        entry.tags = Some(String::from("black metal"));
+        database.upsert(&entry, 200).unwrap();
+        entry.folder = None;
+        database.upsert(&entry, 300).unwrap();
+
+        let folders = database.connection
+            .prepare("SELECT folder, tags FROM bookmarks ORDER BY folder").unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?))).unwrap()
+            .collect::<rusqlite::Result<Vec<(String, Option<String>)>>>().unwrap();
+        assert_eq!(folders, vec![
+            (String::from(""), Some(String::from("black metal"))),
+            (String::from("musik 20151119"), Some(String::from("black metal"))),
+        ]);
+
+        assert!(database.upsert(&RedditEntry::default(), 400).is_err());
+    }
+
+    #[test]
+    fn test_upsert_comments() {
+        let mut database = DatabaseOutput::from_connection(Connection::open_in_memory().unwrap())
+            .expect("could not create schema")
+            .with_comments(true);
+        let json = ::load_json_file("test_resources/5k0ncr.json").expect("could not load json");
+        let entry = ::parse_reddit_json(&json).expect("could not parse thread");
+
+        database.upsert(&entry, 100).unwrap();
+        database.upsert(&entry, 200).unwrap();
+        assert_eq!(count(&database, "comments") as usize,
+                   comments::flatten(&entry.comment_tree).len());
+        assert!(count(&database, "comments") > 0);
+    }
+
+    #[test]
+    fn test_upsert_comment_link() {
+        let mut database = DatabaseOutput::from_connection(Connection::open_in_memory().unwrap())
+            .expect("could not create schema");
+        let mut link = RedditEntry {
+            url: ::parse("https://weakling.bandcamp.com/album/dead-as-dreams"),
+            title: Some(String::from("Weakling - Dead as Dreams")),
+            votes: Some(4),
+            found_in: Some(String::from("5k0ncr")),
+            comment_id: Some(String::from("dbk8xyz")),
+            ..RedditEntry::default()
+        };
+
+        database.upsert(&link, 100).expect("could not insert comment link");
+        link.votes = Some(7);
+        database.upsert(&link, 200).expect("could not update comment link");
+
+        assert_eq!(count(&database, "entries"), 0);
+        assert_eq!(count(&database, "comment_links"), 1);
+        let row: (String, i64, i64) = database.connection
+            .query_row("SELECT reddit_id, score, first_scraped FROM comment_links", [],
+                       |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?))).unwrap();
+        assert_eq!(row, (String::from("5k0ncr"), 7, 100));
+
+        link.comment_id = None;
+        assert!(database.upsert(&link, 300).is_err());
+    }
+}
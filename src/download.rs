@@ -0,0 +1,719 @@
+//! Downloading many links at once without breaking reddit's rate limit.
+//!
+//! A pool of worker threads fetches links while one `RateLimiter`, shared
+//! by all workers, hands out requests from a token bucket per host. All of
+//! reddit's domains share one bucket, other hosts get their own so they
+//! are not held back by reddit's cooldown.
+//!
+//! Every response is shown to the limiter: reddit tells how many requests
+//! are left until its window resets (`X-Ratelimit-Remaining` and
+//! `X-Ratelimit-Reset`) and how long to back off after a 429
+//! (`Retry-After`), the host's bucket is adjusted to match.
+//!
+//! A response only counts as downloaded if it is json in reddit's `Listing`
+//! envelopes. Error statuses, html pages and reddit's json error objects
+//! (`{"reason": "private", "error": 403}`) are classified as errors instead,
+//! so they never end up in the cache.
+//!
+//! Failed requests are retried with a jittered exponential backoff when
+//! the failure looks transient, links that fail for good are collected in
+//! a csv report that can be read as input for a later rerun.
+//!
+//! Results are passed back to the calling thread, so caching and writing
+//! output stay single threaded.
+
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::io::Write;
+use std::sync::mpsc;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use csv;
+use serde_json::{self, Value};
+use url::Url;
+
+/// requests per minute reddit allows clients without oauth
+pub const REDDIT_PER_MINUTE: u32 = 20;
+/// requests per minute for any other host
+pub const OTHER_PER_MINUTE: u32 = 60;
+/// back off after a 429 without a `Retry-After`
+const DEFAULT_BACKOFF: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub status: u32,
+    /// names are lowercase, in the order they were received
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Response {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        let name = name.to_lowercase();
+        self.headers.iter().find(|(n, _)| *n == name).map(|(_, value)| value.as_str())
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.status >= 200 && self.status < 300
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DownloadError {
+    /// not a link that can be downloaded as json
+    InvalidLink(String),
+    /// no response, e.g. a timeout or a refused connection
+    Transfer(String),
+    /// deleted threads and subreddits that never existed
+    NotFound,
+    Private,
+    Quarantined,
+    Banned,
+    RateLimited,
+    /// any other error status
+    Http(u32),
+    /// e.g. an html error page, with the content type or parse error
+    NotJson(String),
+    /// json, but not in reddit's listings
+    NotAListing(String),
+}
+
+impl DownloadError {
+    /// worth another try: server errors, rate limits and failed transfers
+    pub fn is_transient(&self) -> bool {
+        match *self {
+            DownloadError::Transfer(_) | DownloadError::RateLimited => true,
+            DownloadError::Http(status) => status >= 500,
+            _ => false,
+        }
+    }
+
+    fn from_status(status: u32) -> DownloadError {
+        match status {
+            404 => DownloadError::NotFound,
+            429 => DownloadError::RateLimited,
+            status => DownloadError::Http(status),
+        }
+    }
+}
+
+impl fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DownloadError::InvalidLink(ref link) => write!(f, "invalid link {}", link),
+            DownloadError::Transfer(ref e) => write!(f, "transfer failed: {}", e),
+            DownloadError::NotFound => write!(f, "not found"),
+            DownloadError::Private => write!(f, "private subreddit"),
+            DownloadError::Quarantined => write!(f, "quarantined subreddit"),
+            DownloadError::Banned => write!(f, "banned subreddit"),
+            DownloadError::RateLimited => write!(f, "rate limited"),
+            DownloadError::Http(status) => write!(f, "http status {}", status),
+            DownloadError::NotJson(ref found) => write!(f, "not json: {}", found),
+            DownloadError::NotAListing(ref found) => write!(f, "not a reddit listing, found {}", found),
+        }
+    }
+}
+
+impl Error for DownloadError {}
+
+/// reddit's `{"reason": "private", "message": "Forbidden", "error": 403}`,
+/// the reason is left out for plain not found and rate limit errors
+fn reddit_error(value: &Value) -> Option<DownloadError> {
+    let error = value.as_object()?.get("error")?;
+    let reason = value.get("reason").and_then(Value::as_str).unwrap_or("");
+    Some(match reason {
+        "private" => DownloadError::Private,
+        "quarantined" => DownloadError::Quarantined,
+        "banned" => DownloadError::Banned,
+        _ => error.as_u64()
+            .map(|status| DownloadError::from_status(status as u32))
+            .unwrap_or_else(|| DownloadError::NotAListing(format!("error {}", error))),
+    })
+}
+
+/// a thread is an array of listings, an info response a single listing
+fn is_listing(value: &Value) -> bool {
+    let listing = |value: &Value| value.get("kind").and_then(Value::as_str) == Some("Listing");
+    match *value {
+        Value::Array(ref values) => !values.is_empty() && values.iter().all(listing),
+        ref value => listing(value),
+    }
+}
+
+/// the error a response stands for, if any
+pub fn check_response(response: &Response) -> Result<(), DownloadError> {
+    let value = serde_json::from_str::<Value>(&response.body);
+    // NB(nils): the reason in the body tells more than the status
+    if let Some(error) = value.as_ref().ok().and_then(reddit_error) {
+        return Err(error);
+    }
+    if !response.is_success() {
+        return Err(DownloadError::from_status(response.status));
+    }
+    if let Some(content_type) = response.header("content-type") {
+        if !content_type.contains("json") {
+            return Err(DownloadError::NotJson(content_type.to_string()));
+        }
+    }
+
+    let value = value.map_err(|e| DownloadError::NotJson(e.to_string()))?;
+    if is_listing(&value) {
+        Ok(())
+    } else {
+        let mut found = value.to_string();
+        if found.len() > 60 {
+            let end = (0..=60).rev().find(|&i| found.is_char_boundary(i)).unwrap_or(0);
+            found.truncate(end);
+            found.push('…');
+        }
+        Err(DownloadError::NotAListing(found))
+    }
+}
+
+/// the body as text, json is never anything but utf-8
+pub fn response_body(data: Vec<u8>) -> Result<String, DownloadError> {
+    String::from_utf8(data).map_err(|e| DownloadError::NotJson(e.to_string()))
+}
+
+/// a `name: value` header line as received by curl, status lines and
+/// the empty line ending the headers are None
+pub fn parse_header(line: &[u8]) -> Option<(String, String)> {
+    let line = String::from_utf8_lossy(line);
+    let colon = line.find(':')?;
+    let name = line[..colon].trim().to_lowercase();
+    if name.is_empty() || name.contains(' ') {
+        return None;
+    }
+    Some((name, line[colon + 1..].trim().to_string()))
+}
+
+/// `Retry-After` and `X-Ratelimit-Reset` are seconds, reddit sends
+/// the remaining requests as a float, e.g. "598.0"
+fn seconds(value: &str) -> Option<Duration> {
+    value.trim().parse::<f64>().ok()
+        .filter(|s| s.is_finite() && *s >= 0.0)
+        .map(Duration::from_secs_f64)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    /// tokens added per second
+    rate: f64,
+    /// the configured rate, rate limit headers may only lower it
+    max_rate: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    fn new(per_minute: u32, burst: u32, now: Instant) -> TokenBucket {
+        TokenBucket {
+            capacity: f64::from(burst.max(1)),
+            tokens: f64::from(burst.max(1)),
+            rate: f64::from(per_minute.max(1)) / 60.0,
+            max_rate: f64::from(per_minute.max(1)) / 60.0,
+            last: now,
+        }
+    }
+
+    /// takes a token, or tells how long until the next one
+    fn take(&mut self, now: Instant) -> Option<Duration> {
+        if now < self.last {
+            return Some(self.last - now);
+        }
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.last = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            None
+        } else {
+            Some(Duration::from_secs_f64((1.0 - self.tokens) / self.rate))
+        }
+    }
+
+    /// no requests until `until`, then a single one
+    fn block(&mut self, until: Instant) {
+        if until > self.last {
+            self.last = until;
+            self.tokens = 1.0;
+        }
+    }
+}
+
+/// the bucket a url is counted against
+fn host_key(url: &Url) -> String {
+    let host = url.host_str().unwrap_or("").to_lowercase();
+    let is_reddit = |domain: &str| host == domain || host.ends_with(&format!(".{}", domain));
+    if is_reddit("reddit.com") || is_reddit("redd.it") {
+        String::from("reddit.com")
+    } else {
+        host
+    }
+}
+
+pub struct RateLimiter {
+    reddit_per_minute: u32,
+    other_per_minute: u32,
+    /// requests sent at once before the rate applies
+    burst: u32,
+    buckets: Mutex<HashMap<String, TokenBucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(reddit_per_minute: u32, other_per_minute: u32, burst: u32) -> RateLimiter {
+        RateLimiter {
+            reddit_per_minute,
+            other_per_minute,
+            burst,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn with_bucket<F, T>(&self, url: &Url, now: Instant, f: F) -> T
+        where F: FnOnce(&mut TokenBucket) -> T
+    {
+        let key = host_key(url);
+        let per_minute = if key == "reddit.com" {
+            self.reddit_per_minute
+        } else {
+            self.other_per_minute
+        };
+        let mut buckets = self.buckets.lock().expect("rate limiter lock poisoned");
+        f(buckets.entry(key).or_insert_with(|| TokenBucket::new(per_minute, self.burst, now)))
+    }
+
+    /// how long to wait before `url` may be requested, `None` if it may be
+    /// requested now, in which case the request is counted
+    fn try_acquire(&self, url: &Url, now: Instant) -> Option<Duration> {
+        self.with_bucket(url, now, |bucket| bucket.take(now))
+    }
+
+    /// adjusts the host's bucket to the rate limit headers of a response
+    pub fn update(&self, url: &Url, response: &Response) {
+        self.update_at(url, response, Instant::now())
+    }
+
+    fn update_at(&self, url: &Url, response: &Response, now: Instant) {
+        let reset = response.header("x-ratelimit-reset").and_then(seconds);
+        if response.status == 429 {
+            let wait = response.header("retry-after").and_then(seconds)
+                .or(reset)
+                .unwrap_or(DEFAULT_BACKOFF);
+            self.with_bucket(url, now, |bucket| bucket.block(now + wait));
+            return;
+        }
+
+        let remaining = response.header("x-ratelimit-remaining")
+            .and_then(|r| r.trim().parse::<f64>().ok());
+        if let (Some(remaining), Some(reset)) = (remaining, reset) {
+            self.with_bucket(url, now, |bucket| if remaining < 1.0 {
+                bucket.block(now + reset)
+            } else {
+                // NB(nils): spread what is left evenly over the rest of the window
+                bucket.rate = (remaining / reset.as_secs_f64().max(1.0)).min(bucket.max_rate);
+            });
+        }
+    }
+
+    /// blocks until `url` may be requested
+    pub fn acquire(&self, url: &Url) {
+        // NB(nils): the lock is not held while sleeping, another worker
+        // NB(nils): may take the token first and this one waits again
+        while let Some(wait) = self.try_acquire(url, Instant::now()) {
+            thread::sleep(wait);
+        }
+    }
+}
+
+impl Default for RateLimiter {
+    fn default() -> RateLimiter {
+        RateLimiter::new(REDDIT_PER_MINUTE, OTHER_PER_MINUTE, 1)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// tries after the first one
+    pub retries: u32,
+    /// the backoff before the first retry, doubled for every retry after it
+    pub base: Duration,
+    pub max: Duration,
+}
+
+impl RetryPolicy {
+    /// between half and all of the exponential backoff, `jitter` in [0, 1)
+    fn delay(&self, retry: u32, jitter: f64) -> Duration {
+        let backoff = self.base.checked_mul(2u32.saturating_pow(retry))
+            .unwrap_or(self.max)
+            .min(self.max);
+        backoff / 2 + backoff.mul_f64(jitter / 2.0)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy { retries: 3, base: Duration::from_secs(2), max: Duration::from_secs(60) }
+    }
+}
+
+/// a number in [0, 1), different for every call, so workers that
+/// failed together do not all retry at the same moment
+fn jitter() -> f64 {
+    // NB(nils): every RandomState is seeded differently, no need for a rand crate
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u32(SystemTime::now().duration_since(UNIX_EPOCH)
+                     .map(|t| t.subsec_nanos()).unwrap_or(0));
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+pub struct DownloadOptions {
+    /// only download the posts, see `info`
+    pub batch: bool,
+    /// the comments are needed, cached posts without them are downloaded again
+    pub comments: bool,
+    /// downloads in flight at once
+    pub jobs: usize,
+    pub limiter: RateLimiter,
+    pub retry: RetryPolicy,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> DownloadOptions {
+        DownloadOptions {
+            batch: false,
+            comments: false,
+            jobs: 4,
+            limiter: RateLimiter::default(),
+            retry: RetryPolicy::default(),
+        }
+    }
+}
+
+/// fetches `url` until it succeeds, fails for good or runs out of retries,
+/// see `check_response` for what counts as success
+fn fetch_with_retries<F>(url: &Url, options: &DownloadOptions, fetch: &F)
+                         -> Result<Response, DownloadError>
+    where F: Fn(&Url) -> Result<Response, DownloadError>
+{
+    let mut retry = 0;
+    loop {
+        options.limiter.acquire(url);
+        let result = fetch(url).and_then(|response| {
+            options.limiter.update(url, &response);
+            check_response(&response).map(|_| response)
+        });
+        match result {
+            Err(ref e) if e.is_transient() && retry < options.retry.retries => {
+                let delay = options.retry.delay(retry, jitter());
+                println!("retrying {} in {:.1}s: {}", url, delay.as_secs_f64(), e);
+                thread::sleep(delay);
+                retry += 1;
+            },
+            result => return result,
+        }
+    }
+}
+
+/// fetches every url on `options.jobs` threads, `on_done` is called on the
+/// calling thread for each result, in the order they finish
+pub fn download_all<F, G>(urls: Vec<Url>, options: &DownloadOptions, fetch: F, mut on_done: G)
+    where F: Fn(&Url) -> Result<Response, DownloadError> + Sync,
+          G: FnMut(Url, Result<Response, DownloadError>)
+{
+    let queue = Mutex::new(urls.into_iter());
+    let (sender, receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..options.jobs.max(1) {
+            let sender = sender.clone();
+            let (queue, fetch) = (&queue, &fetch);
+            scope.spawn(move || loop {
+                let url = match queue.lock().expect("download queue lock poisoned").next() {
+                    Some(url) => url,
+                    None => break,
+                };
+                let result = fetch_with_retries(&url, options, fetch);
+                if sender.send((url, result)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+
+        for (url, result) in receiver {
+            on_done(url, result);
+        }
+    });
+}
+
+/// a link that could not be scraped, `title` is the bookmark's
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FailedLink {
+    pub url: String,
+    pub title: Option<String>,
+    pub reason: String,
+}
+
+/// the failed links as csv, which can be read back as input
+pub fn write_failed_links<W: Write>(writer: W, failed: &[FailedLink]) -> Result<(), csv::Error> {
+    let mut writer = csv::Writer::from_writer(writer);
+    for link in failed {
+        writer.serialize(link)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn url(url: &str) -> Url {
+        Url::parse(url).expect("could not parse url")
+    }
+
+    fn response(status: u32, headers: &[(&str, &str)]) -> Response {
+        Response {
+            status,
+            headers: headers.iter().map(|&(n, v)| (n.to_lowercase(), v.to_string())).collect(),
+            body: String::from(r#"{"kind": "Listing", "data": {"children": []}}"#),
+        }
+    }
+
+    fn body(status: u32, content_type: &str, body: &str) -> Response {
+        Response { body: body.to_string(), ..response(status, &[("content-type", content_type)]) }
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(60, 2, start);
+
+        assert_eq!(bucket.take(start), None);
+        assert_eq!(bucket.take(start), None);
+        let wait = bucket.take(start).expect("the bucket should be empty");
+        assert!(wait > Duration::from_millis(990) && wait <= Duration::from_secs(1));
+
+        assert_eq!(bucket.take(start + Duration::from_secs(1)), None);
+        // NB(nils): a long pause refills up to the burst, not beyond
+        let later = start + Duration::from_secs(60);
+        assert_eq!(bucket.take(later), None);
+        assert_eq!(bucket.take(later), None);
+        assert!(bucket.take(later).is_some());
+    }
+
+    #[test]
+    fn test_host_key() {
+        assert_eq!(host_key(&url("https://www.reddit.com/comments/5k0ncr/")), "reddit.com");
+        assert_eq!(host_key(&url("https://old.reddit.com/r/Metal/")), "reddit.com");
+        assert_eq!(host_key(&url("https://redd.it/5k0ncr")), "reddit.com");
+        assert_eq!(host_key(&url("https://notreddit.com/")), "notreddit.com");
+        assert_eq!(host_key(&url("https://www.youtube.com/watch?v=bbvBJMDbyeo")), "www.youtube.com");
+    }
+
+    #[test]
+    fn test_hosts_are_limited_separately() {
+        let limiter = RateLimiter::new(1, 60, 1);
+        let now = Instant::now();
+
+        assert_eq!(limiter.try_acquire(&url("https://www.reddit.com/a"), now), None);
+        assert!(limiter.try_acquire(&url("https://old.reddit.com/b"), now).is_some());
+        assert_eq!(limiter.try_acquire(&url("https://aelv.se/a"), now), None);
+    }
+
+    #[test]
+    fn test_download_all() {
+        let urls = (0..20).map(|i| url(&format!("https://example.com/{}", i))).collect::<Vec<_>>();
+        let options = DownloadOptions { limiter: RateLimiter::new(60, 6000, 20), ..DownloadOptions::default() };
+
+        let mut done = Vec::new();
+        download_all(urls.clone(), &options,
+                     |url| Ok(response(200, &[("x-path", url.path())])),
+                     |url, response| done.push((url, response.unwrap())));
+
+        assert_eq!(done.len(), urls.len());
+        assert!(done.iter().all(|(url, response)| response.header("x-path") == Some(url.path())));
+        for url in &urls {
+            assert!(done.iter().any(|(done, _)| done == url));
+        }
+    }
+
+    #[test]
+    fn test_download_all_is_limited() {
+        let urls = (0..3).map(|i| url(&format!("https://www.reddit.com/{}", i))).collect::<Vec<_>>();
+        // NB(nils): one request at once and then one every 0.1 seconds
+        let options = DownloadOptions {
+            jobs: 3,
+            limiter: RateLimiter::new(600, 600, 1),
+            ..DownloadOptions::default()
+        };
+
+        let start = Instant::now();
+        let mut count = 0;
+        download_all(urls, &options, |_| Ok(response(200, &[])), |_, _| count += 1);
+
+        assert_eq!(count, 3);
+        assert!(start.elapsed() >= Duration::from_millis(190));
+    }
+
+    #[test]
+    fn test_parse_header() {
+        assert_eq!(parse_header(b"X-Ratelimit-Remaining: 598.0\r\n"),
+                   Some((String::from("x-ratelimit-remaining"), String::from("598.0"))));
+        assert_eq!(parse_header(b"Date: Fri, 23 Dec 2016 01:31:28 GMT\r\n"),
+                   Some((String::from("date"), String::from("Fri, 23 Dec 2016 01:31:28 GMT"))));
+        assert_eq!(parse_header(b"HTTP/1.1 200 OK\r\n"), None);
+        assert_eq!(parse_header(b"\r\n"), None);
+
+        let response = response(200, &[("retry-after", "7")]);
+        assert_eq!(response.header("Retry-After"), Some("7"));
+        assert_eq!(response.header("x-ratelimit-used"), None);
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let limiter = RateLimiter::new(600, 600, 5);
+        let now = Instant::now();
+        let reddit = url("https://www.reddit.com/comments/5k0ncr/.json");
+
+        limiter.update_at(&reddit, &response(429, &[("Retry-After", "7")]), now);
+        let wait = limiter.try_acquire(&reddit, now).expect("should back off");
+        assert_eq!(wait, Duration::from_secs(7));
+        assert_eq!(limiter.try_acquire(&url("https://aelv.se/"), now), None);
+
+        let later = now + Duration::from_secs(7);
+        assert_eq!(limiter.try_acquire(&reddit, later), None);
+        assert!(limiter.try_acquire(&reddit, later).is_some());
+
+        let other = url("https://aelv.se/a");
+        limiter.update_at(&other, &response(429, &[]), now);
+        assert_eq!(limiter.try_acquire(&other, now), Some(DEFAULT_BACKOFF));
+    }
+
+    #[test]
+    fn test_ratelimit_headers() {
+        let limiter = RateLimiter::new(600, 60, 1);
+        let now = Instant::now();
+        let reddit = url("https://www.reddit.com/comments/5k0ncr/.json");
+        assert_eq!(limiter.try_acquire(&reddit, now), None);
+
+        // NB(nils): 60 requests left for 30 seconds, one every half second
+        limiter.update_at(&reddit, &response(200, &[("x-ratelimit-remaining", "60.0"),
+                                                    ("x-ratelimit-reset", "30")]), now);
+        let wait = limiter.try_acquire(&reddit, now).expect("the bucket should be empty");
+        assert!(wait > Duration::from_millis(100) && wait <= Duration::from_millis(500));
+
+        limiter.update_at(&reddit, &response(200, &[("x-ratelimit-remaining", "0"),
+                                                    ("x-ratelimit-reset", "120")]), now);
+        assert_eq!(limiter.try_acquire(&reddit, now), Some(Duration::from_secs(120)));
+
+        // NB(nils): the headers allow ten a second, but only one a minute is configured
+        let limiter = RateLimiter::new(1, 60, 1);
+        assert_eq!(limiter.try_acquire(&reddit, now), None);
+        limiter.update_at(&reddit, &response(200, &[("x-ratelimit-remaining", "600"),
+                                                    ("x-ratelimit-reset", "60")]), now);
+        let wait = limiter.try_acquire(&reddit, now).expect("the bucket should be empty");
+        assert!(wait > Duration::from_secs(59));
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy { retries: 3, base: Duration::from_secs(2), max: Duration::from_secs(10) };
+        assert_eq!(policy.delay(0, 0.0), Duration::from_secs(1));
+        assert_eq!(policy.delay(1, 0.5), Duration::from_secs(3));
+        assert_eq!(policy.delay(2, 0.0), Duration::from_secs(4));
+        assert_eq!(policy.delay(3, 0.0), Duration::from_secs(5));
+        assert_eq!(policy.delay(40, 0.0), Duration::from_secs(5));
+        assert!((0..100).map(|_| jitter()).all(|j| (0.0..1.0).contains(&j)));
+    }
+
+    #[test]
+    fn test_retries() {
+        let options = DownloadOptions {
+            limiter: RateLimiter::new(6000, 6000, 10),
+            retry: RetryPolicy { retries: 2, base: Duration::from_millis(1), max: Duration::from_millis(2) },
+            ..DownloadOptions::default()
+        };
+        let attempts = Mutex::new(HashMap::new());
+        let fetch = |url: &Url| {
+            let mut attempts = attempts.lock().unwrap();
+            let attempt = attempts.entry(url.path().to_string()).or_insert(0);
+            *attempt += 1;
+            match (url.path(), *attempt) {
+                ("/flaky", 1) => Err(DownloadError::Transfer(String::from("timeout"))),
+                ("/flaky", 2) => Ok(response(503, &[])),
+                ("/missing", _) => Ok(response(404, &[])),
+                ("/down", _) => Ok(response(502, &[])),
+                _ => Ok(response(200, &[])),
+            }
+        };
+
+        let urls = ["/flaky", "/missing", "/down"].iter()
+            .map(|path| url(&format!("https://aelv.se{}", path))).collect();
+        let mut results = HashMap::new();
+        download_all(urls, &options, fetch, |url, result| {
+            results.insert(url.path().to_string(), result.map(|r| r.status));
+        });
+
+        assert_eq!(results["/flaky"], Ok(200));
+        assert_eq!(results["/missing"], Err(DownloadError::NotFound));
+        assert_eq!(results["/down"], Err(DownloadError::Http(502)));
+        let attempts = attempts.lock().unwrap();
+        assert_eq!((attempts["/flaky"], attempts["/missing"], attempts["/down"]), (3, 1, 3));
+    }
+
+    #[test]
+    fn test_write_failed_links() {
+        let failed = vec![FailedLink {
+            url: String::from("https://www.reddit.com/comments/5k0ncr/"),
+            title: Some(String::from("[Black] Weakling - Dead as Dreams")),
+            reason: String::from("http status 503"),
+        }];
+        let mut csv = Vec::new();
+        write_failed_links(&mut csv, &failed).expect("could not write report");
+        assert_eq!(String::from_utf8(csv.clone()).unwrap(),
+                   "url,title,reason\n\
+                    https://www.reddit.com/comments/5k0ncr/,[Black] Weakling - Dead as Dreams,http status 503\n");
+
+        let links = ::input::parse_csv_links(&csv[..]).expect("could not read report");
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].url.as_str(), "https://www.reddit.com/comments/5k0ncr/");
+        assert_eq!(links[0].title, Some(String::from("[Black] Weakling - Dead as Dreams")));
+    }
+
+    #[test]
+    fn test_check_response() {
+        let json = "application/json; charset=UTF-8";
+        let check = |response: Response| check_response(&response);
+
+        assert_eq!(check(body(200, json, r#"[{"kind": "Listing", "data": {}}, {"kind": "Listing"}]"#)), Ok(()));
+        assert_eq!(check(body(200, json, r#"{"kind": "Listing", "data": {"children": []}}"#)), Ok(()));
+        assert_eq!(check(body(404, json, r#"{"message": "Not Found", "error": 404}"#)),
+                   Err(DownloadError::NotFound));
+        assert_eq!(check(body(403, json, r#"{"reason": "private", "message": "Forbidden", "error": 403}"#)),
+                   Err(DownloadError::Private));
+        assert_eq!(check(body(403, json, r#"{"reason": "quarantined", "quarantine_message": "", "error": 403}"#)),
+                   Err(DownloadError::Quarantined));
+        assert_eq!(check(body(404, json, r#"{"reason": "banned", "message": "Not Found", "error": 404}"#)),
+                   Err(DownloadError::Banned));
+        assert_eq!(check(body(429, "text/html", "<h1>too many requests</h1>")),
+                   Err(DownloadError::RateLimited));
+        assert_eq!(check(body(403, "text/html", "<html>blocked</html>")), Err(DownloadError::Http(403)));
+        assert_eq!(check(body(200, "text/html; charset=utf-8", "<html></html>")),
+                   Err(DownloadError::NotJson(String::from("text/html; charset=utf-8"))));
+        assert!(matches!(check(body(200, json, "<html>")), Err(DownloadError::NotJson(_))));
+        assert_eq!(check(body(200, json, r#"{"a": "b"}"#)),
+                   Err(DownloadError::NotAListing(String::from(r#"{"a":"b"}"#))));
+        assert_eq!(check(body(200, json, "[]")), Err(DownloadError::NotAListing(String::from("[]"))));
+        assert_eq!(response_body(b"[]".to_vec()), Ok(String::from("[]")));
+        assert!(matches!(response_body(vec![b'[', 0xff, b']']), Err(DownloadError::NotJson(_))));
+    }
+}
@@ -0,0 +1,97 @@
+//! Reddit's `/api/info.json`, the posts of up to 100 threads in one request.
+//!
+//! The response is a single `Listing` of `t3` things without any comments.
+//! Each post is rewrapped as a thread with an empty comment listing, the
+//! same shape as a `/comments/<id>.json` response, so it can be cached and
+//! parsed like a downloaded thread. It is cached under `post_key`, apart
+//! from full threads, so a later run that wants the comments still
+//! downloads the thread.
+
+use serde_json::{self, Value};
+use url::Url;
+
+use thing::ThreadError;
+
+/// the most ids reddit accepts in one request
+pub const BATCH_SIZE: usize = 100;
+
+/// marks cache keys and files of threads without their comments
+pub const POST_ONLY_SUFFIX: &str = ".post";
+
+/// the cache key of a post downloaded without its comments
+pub fn post_key(id: &str) -> String {
+    format!("{}{}", id, POST_ONLY_SUFFIX)
+}
+
+/// the info url for thread ids, without their `t3_` prefix
+pub fn info_url(ids: &[String]) -> Url {
+    let fullnames = ids.iter().map(|id| format!("t3_{}", id)).collect::<Vec<_>>();
+    let mut url = Url::parse("https://www.reddit.com/api/info.json")
+        .expect("info url is valid");
+    url.query_pairs_mut().append_pair("id", &fullnames.join(","));
+    url
+}
+
+/// the thread id and thread json of every post in an info response
+pub fn split_listing(json: &str) -> Result<Vec<(String, String)>, ThreadError> {
+    let value: Value = serde_json::from_str(json)?;
+    if value.get("kind").and_then(Value::as_str) != Some("Listing") {
+        return Err(ThreadError::NotAThread(String::from("no info listing")));
+    }
+    let children = value.pointer("/data/children").and_then(Value::as_array)
+        .ok_or_else(|| ThreadError::NotAThread(String::from("an info listing without children")))?;
+
+    let mut threads = Vec::with_capacity(children.len());
+    for child in children {
+        if child.get("kind").and_then(Value::as_str) != Some("t3") {
+            continue;
+        }
+        let id = match child.pointer("/data/id").and_then(Value::as_str) {
+            Some(id) => id.to_string(),
+            None => continue,
+        };
+        let thread = format!("[{{\"kind\": \"Listing\", \"data\": {{\"children\": [{}]}}}}, \
+                              {{\"kind\": \"Listing\", \"data\": {{\"children\": []}}}}]",
+                             serde_json::to_string(child)?);
+        threads.push((id, thread));
+    }
+    Ok(threads)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use thing;
+
+    #[test]
+    fn test_info_url() {
+        let url = info_url(&[String::from("5k0ncr"), String::from("3quxqv")]);
+        assert_eq!(url.as_str(), "https://www.reddit.com/api/info.json?id=t3_5k0ncr%2Ct3_3quxqv");
+    }
+
+    #[test]
+    fn test_split_listing() {
+        let json = ::load_json_file("test_resources/5k0ncr.json").expect("could not load json");
+        let thread: Value = serde_json::from_str(&json).unwrap();
+        let post = thread.pointer("/0/data/children/0").unwrap();
+        let info = format!(r#"{{"kind": "Listing", "data": {{"children": [{}, {{"kind": "t3", "data": {{"id": "abc"}}}},
+                                                                          {{"kind": "t5", "data": {{"id": "xyz"}}}}]}}}}"#,
+                           post);
+
+        let threads = split_listing(&info).expect("could not split listing");
+        let ids = threads.iter().map(|(id, _)| id.as_str()).collect::<Vec<_>>();
+        assert_eq!(ids, vec!["5k0ncr", "abc"]);
+
+        let split = thing::parse_thread(&threads[0].1).expect("split thread is not a thread");
+        let original = thing::parse_thread(&json).unwrap();
+        assert_eq!(split.post, original.post);
+        assert!(split.comments.is_empty());
+    }
+
+    #[test]
+    fn test_split_errors() {
+        assert!(split_listing("<html>").is_err());
+        assert!(split_listing(r#"{"error": 403}"#).is_err());
+        assert!(split_listing(r#"{"kind": "Listing", "data": {"children": []}}"#).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,327 @@
+//! Input formats and sniffing which one a file is in.
+//!
+//! Every format is read into a list of `Bookmark`s, formats that carry
+//! no folder or date information simply leave those fields empty.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use csv;
+use url::Url;
+
+use bookmark::Bookmark;
+use places;
+use reddit_export;
+use text::normalize_title;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputFormat {
+    Netscape,
+    Chromium,
+    Places,
+    RedditExport,
+    Plain,
+    Csv,
+    Markdown,
+    /// free text or html pages, every url in it is a link
+    Text,
+}
+
+impl InputFormat {
+    /// names accepted by `--input-format`
+    pub const NAMES: [&'static str; 8] =
+        ["netscape", "chromium", "places", "reddit-export", "plain", "csv", "markdown", "text"];
+
+    pub fn from_name(name: &str) -> Option<InputFormat> {
+        match name {
+            "netscape" => Some(InputFormat::Netscape),
+            "chromium" => Some(InputFormat::Chromium),
+            "places" => Some(InputFormat::Places),
+            "reddit-export" => Some(InputFormat::RedditExport),
+            "plain" => Some(InputFormat::Plain),
+            "csv" => Some(InputFormat::Csv),
+            "markdown" => Some(InputFormat::Markdown),
+            "text" => Some(InputFormat::Text),
+            _ => None,
+        }
+    }
+
+    /// binary formats are recognised by their magic bytes, text formats by
+    /// their first line and the file extension, a list of one link per line
+    /// is plain text and anything else free text
+    pub fn detect<P: AsRef<Path>>(path: P) -> InputFormat {
+        let path = path.as_ref();
+        if places::is_places_database(path) {
+            return InputFormat::Places;
+        }
+        if reddit_export::is_reddit_export(path) {
+            return InputFormat::RedditExport;
+        }
+
+        let mut head = Vec::new();
+        if let Ok(file) = File::open(path) {
+            let _ = file.take(4096).read_to_end(&mut head);
+        }
+        let head = String::from_utf8_lossy(&head);
+        let extension = path.extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        InputFormat::detect_text(&head, &extension)
+    }
+
+    /// detection for content that is not in a file, e.g. stdin,
+    /// `extension` may be empty
+    pub fn detect_text(head: &str, extension: &str) -> InputFormat {
+        let upper = head.to_uppercase();
+        // NB(nils): a snippet of a bookmark file has no doctype
+        if upper.trim_start().starts_with("<!DOCTYPE NETSCAPE-BOOKMARK-FILE-1")
+            || upper.contains("<DT><A ") {
+            return InputFormat::Netscape;
+        }
+        let trimmed = head.trim_start();
+        if trimmed.starts_with('{') && head.contains("\"roots\"") {
+            return InputFormat::Chromium;
+        }
+
+        match extension {
+            "csv" => return InputFormat::Csv,
+            "md" | "markdown" => return InputFormat::Markdown,
+            "html" | "htm" => return InputFormat::Text,
+            _ => {},
+        }
+
+        if head.contains("](http") {
+            InputFormat::Markdown
+        } else if head.lines().all(|line| !line.trim().contains(char::is_whitespace)) {
+            InputFormat::Plain
+        } else {
+            InputFormat::Text
+        }
+    }
+}
+
+/// every cell that holds an http(s) url is a link,
+/// a `title` column is used as the title of the links on its row
+pub fn parse_csv_links<R: Read>(reader: R) -> Result<Vec<Bookmark>, csv::Error> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(reader);
+    let title_column = reader.headers()?.iter()
+        .position(|header| header.trim().eq_ignore_ascii_case("title"));
+
+    let mut bookmarks = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let title = title_column.and_then(|i| record.get(i))
+            .map(normalize_title)
+            .filter(|title| !title.is_empty());
+
+        for cell in record.iter() {
+            let url = match Url::parse(cell.trim()) {
+                Ok(ref url) if url.scheme() == "http" || url.scheme() == "https" => url.clone(),
+                _ => continue,
+            };
+            let mut bookmark = Bookmark::from_url(url);
+            bookmark.title = title.clone();
+            bookmarks.push(bookmark);
+        }
+    }
+    Ok(bookmarks)
+}
+
+const URL_SCHEMES: [&str; 2] = ["https://", "http://"];
+
+/// every http(s) url in free text, markdown or html,
+/// titles are taken from markdown `[title](url)` and html `<a href="url">title</a>`
+pub fn extract_links(text: &str) -> Vec<Bookmark> {
+    // NB(nils): ascii lowercasing keeps the byte offsets of `text`
+    let lower = text.to_ascii_lowercase();
+    let mut bookmarks = Vec::new();
+    let mut pos = 0;
+
+    while let Some(start) = next_url_start(&lower, pos) {
+        let end = start + text[start..]
+            .find(|c: char| c.is_whitespace() || "<>\"'`".contains(c))
+            .unwrap_or(text.len() - start);
+        pos = end;
+
+        let candidate = trim_url(&text[start..end]);
+        let url = match Url::parse(&candidate.replace("&amp;", "&")) {
+            Ok(url) => url,
+            Err(_) => continue,
+        };
+        let mut bookmark = Bookmark::from_url(url);
+        bookmark.title = markdown_title(&text[..start])
+            .or_else(|| html_title(&lower[..start], text, start + candidate.len()))
+            .map(|title| normalize_title(&title))
+            .filter(|title| !title.is_empty());
+        bookmarks.push(bookmark);
+    }
+
+    bookmarks
+}
+
+fn next_url_start(lower: &str, from: usize) -> Option<usize> {
+    URL_SCHEMES.iter()
+        .filter_map(|scheme| lower[from..].find(scheme))
+        .min()
+        .map(|i| from + i)
+}
+
+/// strip punctuation that ends the sentence rather than the url,
+/// closing brackets are kept if the url opened them, e.g. wikipedia links
+fn trim_url(url: &str) -> &str {
+    let mut url = url;
+    loop {
+        let trimmed = url.trim_end_matches(|c: char| ".,;:!?*".contains(c));
+        let trimmed = match trimmed.chars().last() {
+            Some(close @ ')') | Some(close @ ']') => {
+                let open = if close == ')' { '(' } else { '[' };
+                if trimmed.matches(open).count() < trimmed.matches(close).count() {
+                    &trimmed[..trimmed.len() - 1]
+                } else {
+                    trimmed
+                }
+            },
+            _ => trimmed,
+        };
+        if trimmed == url {
+            return url;
+        }
+        url = trimmed;
+    }
+}
+
+/// the `[title]` of a markdown link, given the text up to the url
+fn markdown_title(before: &str) -> Option<String> {
+    let before = before.strip_suffix('<').unwrap_or(before);
+    let before = before.strip_suffix("](")?;
+    let open = before.rfind('[')?;
+    let title = before[open + 1..].trim();
+    // NB(nils): a title does not span paragraphs or nested brackets
+    if title.is_empty() || title.contains(']') || title.contains("\n\n") {
+        return None;
+    }
+    Some(title.to_string())
+}
+
+/// the text of a html link, given the lowercased text up to the url
+/// and where the url ends
+fn html_title(lower_before: &str, text: &str, url_end: usize) -> Option<String> {
+    let attribute = lower_before.trim_end_matches(['"', '\'']);
+    if !attribute.trim_end().ends_with("href=") {
+        return None;
+    }
+
+    let rest = &text[url_end..];
+    let title_start = rest.find('>')? + 1;
+    let title_end = rest.to_ascii_lowercase().find("</a")?;
+    if title_end < title_start {
+        return None;
+    }
+
+    // drop any markup inside the link, e.g. <b>
+    let mut title = String::new();
+    let mut in_tag = false;
+    for c in rest[title_start..title_end].chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            c if !in_tag => title.push(c),
+            _ => {},
+        }
+    }
+    let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+    if title.is_empty() { None } else { Some(title) }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(InputFormat::detect("test_resources/example_bookmark.html"), InputFormat::Netscape);
+        assert_eq!(InputFormat::detect("test_resources/bookmark_entry.txt"), InputFormat::Netscape);
+        assert_eq!(InputFormat::detect("test_resources/example_links.txt"), InputFormat::Plain);
+        assert_eq!(InputFormat::detect("test_resources/chromium_bookmarks"), InputFormat::Chromium);
+        assert_eq!(InputFormat::detect("test_resources/places.sqlite"), InputFormat::Places);
+        assert_eq!(InputFormat::detect("test_resources/reddit_export.zip"), InputFormat::RedditExport);
+        assert_eq!(InputFormat::detect("test_resources/reddit_export/saved_posts.csv"), InputFormat::Csv);
+        assert_eq!(InputFormat::detect("test_resources/example_links.md"), InputFormat::Markdown);
+    }
+
+    #[test]
+    fn test_detect_text() {
+        assert_eq!(InputFormat::detect_text("see [this](https://redd.it/5k0ncr)", ""), InputFormat::Markdown);
+        assert_eq!(InputFormat::detect_text("https://redd.it/5k0ncr\n", "txt"), InputFormat::Plain);
+        assert_eq!(InputFormat::detect_text("<DL><p>\n<dt><a HREF=\"\">", "html"), InputFormat::Netscape);
+        assert_eq!(InputFormat::detect_text("[12:01] <nils> try https://redd.it/5k0ncr\n", "log"), InputFormat::Text);
+        assert_eq!(InputFormat::detect_text("<html><a href=\"https://redd.it/5k0ncr\">", "html"), InputFormat::Text);
+    }
+
+    #[test]
+    fn test_format_names() {
+        for name in InputFormat::NAMES.iter() {
+            assert!(InputFormat::from_name(name).is_some(), "{}", name);
+        }
+        assert_eq!(InputFormat::from_name("auto"), None);
+    }
+
+    #[test]
+    fn test_parse_csv_links() {
+        let input = "title,url,votes\n\
+                     Weakling,https://www.youtube.com/watch?v=bbvBJMDbyeo,83\n\
+                     ,not a link,\n";
+        let bookmarks = parse_csv_links(input.as_bytes()).expect("could not parse csv");
+
+        assert_eq!(bookmarks.len(), 1);
+        assert_eq!(bookmarks[0].url.as_str(), "https://www.youtube.com/watch?v=bbvBJMDbyeo");
+        assert_eq!(bookmarks[0].title, Some(String::from("Weakling")));
+    }
+
+    #[test]
+    fn test_extract_links_free_text() {
+        let text = "[12:01] <nils> listen to this https://redd.it/5k0ncr. or (see \
+                    https://en.wikipedia.org/wiki/Weakling_(band)), also HTTPS://www.youtube.com/watch?v=bbvBJMDbyeo!\n\
+                    not a link: http:// or www.youtube.com";
+        let urls = extract_links(text).iter().map(|b| b.url.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(urls, vec![
+            "https://redd.it/5k0ncr",
+            "https://en.wikipedia.org/wiki/Weakling_(band)",
+            "https://www.youtube.com/watch?v=bbvBJMDbyeo",
+        ]);
+    }
+
+    #[test]
+    fn test_extract_links_html() {
+        let html = "<p>Found <a class=\"title\" href=\"https://www.reddit.com/r/Metal/comments/5k0ncr/?a=1&amp;b=2\">\
+                    <b>Weakling</b> - Dead as Dreams</a> and <A HREF='https://redd.it/5elhkp'></A></p>";
+        let bookmarks = extract_links(html);
+
+        assert_eq!(bookmarks.len(), 2);
+        assert_eq!(bookmarks[0].url.as_str(), "https://www.reddit.com/r/Metal/comments/5k0ncr/?a=1&b=2");
+        assert_eq!(bookmarks[0].title, Some(String::from("Weakling - Dead as Dreams")));
+        assert_eq!(bookmarks[1].url.as_str(), "https://redd.it/5elhkp");
+        assert_eq!(bookmarks[1].title, None);
+    }
+
+    #[test]
+    fn test_extract_markdown_links() {
+        let mut file = File::open("test_resources/example_links.md").expect("could not open markdown");
+        let mut text = String::new();
+        file.read_to_string(&mut text).expect("could not read markdown");
+
+        let bookmarks = extract_links(&text);
+        let urls = bookmarks.iter().map(|b| b.url.as_str()).collect::<Vec<_>>();
+        assert_eq!(urls, vec![
+            "https://www.reddit.com/r/Metal/comments/5k0ncr/black_weakling_dead_as_dreams/",
+            "https://www.reddit.com/r/BlackMetal/comments/5elhkp/spectral_lore_cosmic_significance/",
+            "https://www.youtube.com/watch?v=o_3jJG_oGSs",
+        ]);
+        assert_eq!(bookmarks[0].title, Some(String::from("Weakling - Dead as Dreams")));
+        assert_eq!(bookmarks[1].title, Some(String::from("spectral lore one")));
+        assert_eq!(bookmarks[2].title, None);
+    }
+}
@@ -1,12 +1,33 @@
 extern crate clap;
 extern crate csv;
+extern crate glob;
+extern crate rusqlite;
 extern crate curl;
 extern crate serde_json;
 extern crate time;
+extern crate unicode_normalization;
 extern crate url;
+extern crate zip;
 extern crate serde;
 #[macro_use] extern crate serde_derive;
 
+mod bookmark;
+mod comments;
+mod database;
+mod download;
+mod info;
+mod input;
+mod music;
+mod output;
+mod playlist;
+mod places;
+mod post;
+mod reddit_export;
+mod reddit_link;
+mod report;
+mod text;
+mod thing;
+
 use std::ffi::OsStr;
 use std::fs::File;
 use std::io::BufReader;
@@ -20,6 +41,16 @@ use clap::{App,Arg};
 use curl::easy::Easy;
 use url::Url;
 
+use bookmark::{Bookmark, BookmarkFilter};
+use comments::CommentNode;
+use download::{DownloadError, DownloadOptions, FailedLink, Response};
+use input::InputFormat;
+use output::OutputFormat;
+use post::Post;
+use reddit_link::RedditLink;
+use report::GroupBy;
+use thing::ThreadError;
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 struct Link(Url); // TODO(nils): instead of using Link in all locations
                   // TODO(nils): it might be easier to make a
@@ -46,15 +77,46 @@ fn parse(input: &str) -> Option<Link> {
     Url::parse(input).ok().map(|x| Link(x))
 }
 
-#[derive(Debug, PartialEq, Eq, Serialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
 struct RedditEntry {
     url: Option<Link>,
     reddit_id: Option<String>,
     title: Option<String>,
     subreddit: Option<String>,
-    votes: Option<u64>,
+    votes: Option<i64>,
     comments: Option<u64>,
     self_link: Option<Link>,
+    bookmark_title: Option<String>,
+    folder: Option<String>,
+    added: Option<i64>,
+    tags: Option<String>,
+    genre: Option<String>,
+    artist: Option<String>,
+    track: Option<String>,
+    year: Option<u16>,
+    country: Option<String>,
+    ffo: Option<String>,
+    author: Option<String>,
+    created_utc: Option<i64>,
+    domain: Option<String>,
+    over_18: Option<bool>,
+    flair: Option<String>,
+    gilded: Option<u64>,
+    upvote_ratio: Option<f64>,
+    is_self: Option<bool>,
+    selftext: Option<String>,
+    media_provider: Option<String>,
+    media_title: Option<String>,
+    media_author: Option<String>,
+    media_thumbnail: Option<String>,
+    media_html: Option<String>,
+    /// for links found in comments, the thread's reddit id
+    found_in: Option<String>,
+    comment_id: Option<String>,
+    comment_author: Option<String>,
+    /// written separately from the other fields, see `output`
+    #[serde(skip)]
+    comment_tree: Vec<CommentNode>,
 }
 
 impl RedditEntry {
@@ -67,10 +129,150 @@ impl RedditEntry {
             url:       parse("https://www.youtube.com/watch?v=bbvBJMDbyeo"),
             reddit_id: Some(String::from("5k0ncr")),
             self_link: parse("https://www.reddit.com/r/Metal/comments/5k0ncr/black_weakling_dead_as_dreams/"),
+            bookmark_title: None,
+            folder:    None,
+            added:     None,
+            tags:      None,
+            genre:     Some(String::from("Black")),
+            artist:    Some(String::from("Weakling")),
+            track:     Some(String::from("Dead as Dreams")),
+            year:      None,
+            country:   None,
+            ffo:       None,
+            author:    Some(String::from("sakyamuni_lotus777")),
+            created_utc: Some(1482543088),
+            domain:    Some(String::from("youtube.com")),
+            over_18:   Some(false),
+            flair:     None,
+            gilded:    Some(0),
+            upvote_ratio: Some(0.91),
+            is_self:   Some(false),
+            selftext:  None,
+            media_provider: Some(String::from("YouTube")),
+            media_title: Some(String::from("Weakling - Dead as Dreams")),
+            media_author: Some(String::from("Baldersbalet")),
+            media_thumbnail: Some(String::from("https://i.ytimg.com/vi/bbvBJMDbyeo/hqdefault.jpg")),
+            media_html: Some(String::from("<iframe width=\"459\" height=\"344\" src=\"https://www.youtube.com/embed/bbvBJMDbyeo?feature=oembed\" frameborder=\"0\" allowfullscreen></iframe>")),
+            found_in:  None,
+            comment_id: None,
+            comment_author: None,
+            comment_tree: vec![],
         }
     }
+
+    /// the entry for a post of the json api, html in titles and
+    /// text is decoded
+    fn from_post(post: Post) -> RedditEntry {
+        let permalink = post.permalink.as_ref()
+            .and_then(|p| Url::parse(&format!("https://www.reddit.com{}", p)).ok())
+            .map(Link);
+        let oembed = post.oembed().cloned().unwrap_or_default();
+        let decode = |text: Option<String>| text.map(|t| text::decode_entities(&t))
+            .filter(|t| !t.is_empty());
+
+        let mut entry = RedditEntry {
+            url:       post.url.as_ref().and_then(|u| Url::parse(u).ok()).map(Link),
+            reddit_id: post.id,
+            title:     post.title.map(|t| text::normalize_title(&t)),
+            subreddit: post.subreddit,
+            votes:     post.score,
+            comments:  post.num_comments,
+            self_link: permalink,
+            author:    post.author,
+            created_utc: post.created_utc.map(|t| t as i64),
+            domain:    post.domain,
+            over_18:   post.over_18,
+            flair:     decode(post.link_flair_text),
+            gilded:    post.gilded,
+            upvote_ratio: post.upvote_ratio,
+            is_self:   post.is_self,
+            selftext:  decode(post.selftext),
+            media_provider: oembed.provider_name,
+            media_title: oembed.title.map(|t| text::normalize_title(&t)),
+            media_author: oembed.author_name,
+            media_thumbnail: oembed.thumbnail_url,
+            media_html: decode(oembed.html),
+            ..RedditEntry::default()
+        };
+        entry.add_music_title();
+        entry
+    }
+
+    /// secondary entries for the music links posted in the comments,
+    /// attributed to this thread and its bookmark
+    fn comment_link_entries(&self) -> Vec<RedditEntry> {
+        let thread_id = match self.reddit_id {
+            Some(ref id) => id,
+            None => return vec![],
+        };
+        let own_url = self.url.as_ref().map(|link| &link.0);
+
+        comments::music_links(&self.comment_tree).into_iter()
+            .filter(|(_, link)| Some(&link.url) != own_url)
+            .map(|(comment, link)| {
+                let permalink = comment.id.as_ref().and_then(|id| {
+                    Url::parse(&format!("https://www.reddit.com/comments/{}/_/{}/", thread_id, id)).ok()
+                });
+                let mut entry = RedditEntry {
+                    url:       Some(Link(link.url)),
+                    title:     link.title,
+                    subreddit: self.subreddit.clone(),
+                    votes:     comment.score,
+                    self_link: permalink.map(Link),
+                    bookmark_title: self.bookmark_title.clone(),
+                    folder:    self.folder.clone(),
+                    added:     self.added,
+                    tags:      self.tags.clone(),
+                    created_utc: comment.created_utc,
+                    found_in:  Some(thread_id.clone()),
+                    comment_id: comment.id.clone(),
+                    comment_author: comment.author.clone(),
+                    ..RedditEntry::default()
+                };
+                entry.add_music_title();
+                entry
+            })
+            .collect()
+    }
+
+    /// split the title into music metadata,
+    /// genres and "for fans of" artists are joined with ","
+    fn add_music_title(&mut self) {
+        let music = match self.title {
+            Some(ref title) => music::parse(title),
+            None => return,
+        };
+        let join = |list: Vec<String>| if list.is_empty() {
+            None
+        } else {
+            Some(list.join(","))
+        };
+        self.genre = join(music.genres);
+        self.artist = music.artist;
+        self.track = Some(music.track).filter(|track| !track.is_empty());
+        self.year = music.year;
+        self.country = music.country;
+        self.ffo = join(music.ffo);
+    }
+
+    /// fill in where the entry was bookmarked
+    fn add_bookmark(&mut self, bookmark: &Bookmark) {
+        self.bookmark_title = bookmark.title.clone();
+        self.folder = if bookmark.folder.is_empty() {
+            None
+        } else {
+            Some(bookmark.folder_path())
+        };
+        self.added = bookmark.add_date;
+        self.tags = if bookmark.tags.is_empty() {
+            None
+        } else {
+            Some(bookmark.tags.join(","))
+        };
+    }
 }
 
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::collections::HashSet;
 
@@ -143,14 +345,22 @@ impl Cache {
 
                 let result = result.into_iter()
                     .filter(|file_path| file_path.extension() == Some(OsStr::new("json")))
-                    .flat_map(|json_file| load_json_file(json_file))
                     .collect::<Vec<_>>();
 
                 let mut storage = HashMap::new();
-                for json in result {
-                    parse_reddit_json(&json).map(|reddit|
-                                                reddit.reddit_id.map(|id| storage.insert(id, json))
-                                                );
+                for json_file in result {
+                    // NB(nils): posts without comments are kept under their own key
+                    let post_only = json_file.file_stem()
+                        .is_some_and(|stem| stem.to_string_lossy().ends_with(info::POST_ONLY_SUFFIX));
+                    let json = match load_json_file(&json_file) {
+                        Some(json) => json,
+                        None => continue,
+                    };
+                    let id = parse_reddit_json(&json).ok().and_then(|reddit| reddit.reddit_id);
+                    if let Some(id) = id {
+                        let key = if post_only { info::post_key(&id) } else { id };
+                        storage.insert(key, json);
+                    }
                 }
 
                 let storage = storage; // immutable
@@ -183,8 +393,14 @@ where T: AsRef<Path> {
     file.write_all(json.as_bytes())
 }
 
+/// the thread id for any form of reddit link, see RedditLink,
+/// for other links the second to last path segment
 /// closely tied to filename_from_link
 fn id_from_link(link: &Url) -> Option<String> {
+    if let Some(reddit_link) = RedditLink::parse(link) {
+        return Some(reddit_link.id);
+    }
+
     let path_vec = link.path_segments().map(|c| c.collect::<Vec<_>>());
     let mut path_vec = match path_vec {
         Some(path_vec) => path_vec,
@@ -234,78 +450,207 @@ macro_rules! unwrap_or_skip {
     }
 }
 
-fn parse_song_links_from_plain(file: &File) -> Vec<Url> {
+fn parse_song_links_from_plain<R: Read>(file: R) -> Vec<Url> {
     let some_identity_function = |s: String| Some(s.clone());
     parse_song_links_from_file(file, some_identity_function)
 }
 
-fn bookmark_cleanup(line: String) -> Option<String>{
-    line.split('"').nth(1).map(|s| s.to_string())
+fn parse_song_links_from_bookmark<R: Read>(bookmark: R) -> Vec<Bookmark> {
+    bookmark::read_bookmarks(bookmark)
+}
+
+/// the text formats, which can be read from any reader, e.g. stdin
+fn parse_song_links_from_reader<R: Read>(mut reader: R, format: InputFormat)
+    -> Result<Vec<Bookmark>, Box<dyn std::error::Error>>
+{
+    let mut read_to_string = || -> Result<String, Error> {
+        let mut content = String::new();
+        reader.read_to_string(&mut content)?;
+        Ok(content)
+    };
+
+    let bookmarks = match format {
+        InputFormat::Netscape => parse_song_links_from_bookmark(reader),
+        InputFormat::Chromium => bookmark::parse_chromium_bookmarks(&read_to_string()?)?,
+        InputFormat::Plain => parse_song_links_from_plain(reader)
+            .into_iter().map(Bookmark::from_url).collect(),
+        InputFormat::Csv => input::parse_csv_links(reader)?,
+        InputFormat::Markdown | InputFormat::Text => input::extract_links(&read_to_string()?),
+        InputFormat::Places | InputFormat::RedditExport =>
+            return Err(format!("{:?} can only be read from a file", format).into()),
+    };
+    Ok(bookmarks)
 }
 
-fn parse_song_links_from_bookmark(bookmark: &File) -> Vec<Url> {
-    parse_song_links_from_file(bookmark, bookmark_cleanup)
+/// common entry point for all input formats
+fn parse_song_links<P>(path: P, format: InputFormat)
+    -> Result<Vec<Bookmark>, Box<dyn std::error::Error>>
+    where P: AsRef<Path>
+{
+    let path = path.as_ref();
+    match format {
+        InputFormat::Places => Ok(places::read_places(path)?),
+        InputFormat::RedditExport => Ok(reddit_export::read_reddit_export(path)?),
+        _ => parse_song_links_from_reader(File::open(path)?, format),
+    }
 }
 
-// TODO(nils): don't throttle if the func did not return
-fn throttle<F, A, B>(previous: time::Tm, func: F, arg: A) -> (B, time::Tm)
-    where F: Fn(A) -> B {
-        let duration = time::Duration::seconds(3); // reddit's cooldown rule
-        let time_diff = time::now() - previous;
-        let zero = time::Duration::seconds(0) ;
-
-        assert!(time_diff > zero);
-        let sleep_duration = duration - time_diff;
-        if sleep_duration > zero {
-            std::thread::sleep(sleep_duration.to_std().expect("duration error"));
+/// every input is read with `format`, or with its detected format if None,
+/// directories are read recursively and `-` reads stdin
+fn collect_song_links(inputs: &[&str], format: Option<InputFormat>, verbose: bool)
+    -> Vec<Bookmark>
+{
+    let mut files: Vec<PathBuf> = Vec::new();
+    let mut bookmarks: Vec<Bookmark> = Vec::new();
+
+    for input in inputs {
+        if *input == "-" {
+            let mut content = Vec::new();
+            if let Err(e) = std::io::stdin().read_to_end(&mut content) {
+                println!("could not read stdin: {}, skipping", e);
+                continue;
+            }
+
+            let format = format.unwrap_or_else(|| InputFormat::detect_text(
+                    &String::from_utf8_lossy(&content), ""));
+            if verbose {
+                println!("reading stdin as {:?}", format);
+            }
+            let parsed = parse_song_links_from_reader(&content[..], format);
+            unwrap_or_skip!(parsed, "skipping stdin, could not read it:");
+            bookmarks.extend(parsed);
+        } else {
+            files.extend(expand_directory(Path::new(input)));
         }
+    }
 
-        let result = func(arg);
-        (result, time::now())
+    for file in files {
+        let format = format.unwrap_or_else(|| InputFormat::detect(&file));
+        if verbose {
+            println!("reading {} as {:?}", file.display(), format);
+        }
+        let parsed = parse_song_links(&file, format);
+        unwrap_or_skip!(parsed, format!("skipping {}, could not read it:", file.display()));
+        bookmarks.extend(parsed);
     }
 
-/// funny tuple to facilitate throttle wrapper function
-fn download_reddit_and_cache(tup: (&Url, &mut Option<&mut Cache>)) -> Option<RedditEntry>
-{
-    let (url, cache) = tup;
-    let cache: &mut Option<&mut Cache> = cache;
+    // NB(nils): the first occurrence of a link wins,
+    // NB(nils): so earlier inputs take precedence for folder and date
+    let mut seen = HashSet::new();
+    bookmarks.retain(|bookmark| seen.insert(reddit_link::normalize(&bookmark.url)));
+    bookmarks
+}
 
-    let json = match download_json(url) {
-        Some(json) => json,
-        None => return None,
-    };
+/// the files in a directory, sorted and without hidden files,
+/// a reddit data export directory is kept as a single input
+fn expand_directory(path: &Path) -> Vec<PathBuf> {
+    if !path.is_dir() || reddit_export::is_reddit_export(path) {
+        return vec![path.to_path_buf()];
+    }
 
-    match cache {
-        &mut Some(ref mut cache) => {
-            if let Some(key) = id_from_link(url) {
-                let _ = cache.store(key, &json);
-            };
+    let mut entries = match std::fs::read_dir(path) {
+        Ok(entries) => entries.filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|entry| !entry.file_name()
+                    .is_some_and(|name| name.to_string_lossy().starts_with('.')))
+            .collect::<Vec<_>>(),
+        Err(e) => {
+            println!("could not read directory {} {}", path.display(), e);
+            return vec![];
         },
-        &mut None => { ; },
     };
+    entries.sort();
+    entries.iter().flat_map(|entry| expand_directory(entry)).collect()
+}
+
+/// reads a downloaded thread and caches it under its id,
+/// json that is not a thread is not cached
+fn cache_and_parse(url: &Url, json: Json, cache: &mut Option<&mut Cache>)
+                   -> Result<RedditEntry, ThreadError>
+{
+    let reddit = parse_reddit_json(&json)?;
+    if let Some(ref mut cache) = *cache {
+        if let Some(key) = id_from_link(url) {
+            let _ = cache.store(key, &json);
+        }
+    }
+    Ok(reddit)
+}
 
-    parse_reddit_json(&json)
+/// like cache_and_parse, for the response to an info::info_url,
+/// each post is cached under its info::post_key
+fn cache_and_parse_batch(json: Json, cache: &mut Option<&mut Cache>)
+    -> Result<HashMap<String, Result<RedditEntry, ThreadError>>, ThreadError>
+{
+    let threads = info::split_listing(&json)?;
+
+    let mut reddits = HashMap::with_capacity(threads.len());
+    for (id, json) in threads {
+        let reddit = parse_reddit_json(&json);
+        if let Some(ref mut cache) = *cache {
+            if reddit.is_ok() {
+                let _ = cache.store(info::post_key(&id), &json);
+            }
+        }
+        reddits.insert(id, reddit);
+    }
+    Ok(reddits)
 }
 
-fn bookmark_to_reddit(bookmark: &File, cache: Option<&mut Cache>) -> Vec<RedditEntry> {
-    let mut links = parse_song_links_from_bookmark(bookmark);
-    links.retain(|link| link.host_str() == Some("www.reddit.com"));
+/// `on_entry` is called for every entry as soon as it is read from the cache
+/// or downloaded, so output can be written while the download is running,
+/// links that could not be downloaded or read are returned with the reason
+fn bookmark_to_reddit<F>(mut bookmarks: Vec<Bookmark>, filter: &BookmarkFilter,
+                         cache: Option<&mut Cache>, options: &DownloadOptions, mut on_entry: F)
+                         -> (Vec<RedditEntry>, Vec<FailedLink>)
+    where F: FnMut(&RedditEntry)
+{
+    bookmarks.retain(|bookmark| RedditLink::parse(&bookmark.url).is_some()
+                     && filter.matches(bookmark));
+    let links = bookmarks.iter().map(|bookmark| reddit_link::normalize(&bookmark.url))
+        .collect::<Vec<Url>>();
     let mut reddits: Vec<RedditEntry> = Vec::new();
 
+    let bookmarks_by_id = bookmarks.iter()
+        .filter_map(|bookmark| id_from_link(&bookmark.url).map(|id| (id, bookmark)))
+        .collect::<HashMap<String, &Bookmark>>();
+    let mut found = |mut reddit: RedditEntry, reddits: &mut Vec<RedditEntry>| {
+        let bookmark = reddit.reddit_id.as_ref().and_then(|id| bookmarks_by_id.get(id));
+        if let Some(bookmark) = bookmark {
+            reddit.add_bookmark(bookmark);
+        }
+        on_entry(&reddit);
+        reddits.push(reddit);
+    };
+    let mut failed: Vec<FailedLink> = Vec::new();
+    let failure = |id: &str, reason: String| {
+        println!("could not scrape {}: {}", id, reason);
+        let bookmark = bookmarks_by_id.get(id);
+        FailedLink {
+            url: bookmark.map_or_else(|| format!("https://www.reddit.com/comments/{}/", id),
+                                      |bookmark| bookmark.url.to_string()),
+            title: bookmark.and_then(|bookmark| bookmark.title.clone()),
+            reason,
+        }
+    };
+
     println!("url count to download: {}", links.len()); // DEBUG
 
     let mut cache = match cache {
         Some(cache) => {
-            reddits.extend(get_entries(&links, &cache)
-                           .iter().filter_map(parse_reddit_json)
-                                  .collect::<Vec<RedditEntry>>());
+            for json in get_entries(&links, cache, options.comments) {
+                match parse_reddit_json(&json) {
+                    Ok(reddit) => found(reddit, &mut reddits),
+                    Err(e) => println!("skipping cached thread: {}", e),
+                }
+            }
             Some(cache)
         },
         None => None,
     };
 
-    let links_found_in_cache = reddits.iter().filter_map(|r| r.self_link.clone())
-                                            .collect::<HashSet<Link>>();
+    let links_found_in_cache = reddits.iter()
+        .filter_map(|r| r.self_link.as_ref().map(|Link(url)| Link(reddit_link::normalize(url))))
+        .collect::<HashSet<Link>>();
     let links_set = links.into_iter().map(|x| Link(x)).collect::<HashSet<Link>>();
     let missing_links: Vec<&Link> = links_set.difference(&links_found_in_cache)
         .into_iter().collect();
@@ -313,26 +658,57 @@ fn bookmark_to_reddit(bookmark: &File, cache: Option<&mut Cache>) -> Vec<RedditE
     println!("missing url count to download: {} (not cached)",
         missing_links.len()); // DEBUG(nils)
 
-    let mut previous = time::now();
-    for link in missing_links {
-        let &Link(ref url) = link;
-        let tup = throttle(previous, download_reddit_and_cache, (url, &mut cache));
-        previous = tup.1;
+    if options.batch {
+        let ids = missing_links.iter().filter_map(|link| id_from_link(&link.0))
+            .collect::<Vec<_>>();
+        let urls = ids.chunks(info::BATCH_SIZE).map(info::info_url).collect::<Vec<_>>();
+        let batches = urls.iter().cloned().zip(ids.chunks(info::BATCH_SIZE))
+            .collect::<HashMap<Url, &[String]>>();
+        download::download_all(urls, options, download_json, |url, result| {
+            let ids = batches[&url];
+            let threads = result.map_err(|e| e.to_string()).and_then(|response| {
+                cache_and_parse_batch(response.body, &mut cache).map_err(|e| e.to_string())
+            });
+            let mut threads = match threads {
+                Ok(threads) => threads,
+                Err(reason) => {
+                    failed.extend(ids.iter().map(|id| failure(id, reason.clone())));
+                    return;
+                },
+            };
+            println!("downloaded {} posts", threads.len());
+            for id in ids {
+                match threads.remove(id) {
+                    Some(Ok(reddit)) => found(reddit, &mut reddits),
+                    Some(Err(e)) => failed.push(failure(id, e.to_string())),
+                    None => failed.push(failure(id, String::from("not found, deleted or private"))),
+                }
+            }
+        });
+        return (reddits, failed);
+    }
 
-        let reddit = tup.0;
+    let urls = missing_links.into_iter().map(|link| link.0.clone()).collect::<Vec<_>>();
+    download::download_all(urls, options, download_json, |url, result| {
+        let reddit = result.map_err(|e| e.to_string()).and_then(|response| {
+            cache_and_parse(&url, response.body, &mut cache).map_err(|e| e.to_string())
+        });
         match reddit {
-            Some(reddit) => {
+            Ok(reddit) => {
                 println!("downloaded: {:?}", reddit.self_link);
-                reddits.push(reddit);
+                found(reddit, &mut reddits);
             },
-            None => continue,
-        };
-    }
+            Err(reason) => {
+                let id = id_from_link(&url).unwrap_or_else(|| url.to_string());
+                failed.push(failure(&id, reason));
+            },
+        }
+    });
 
-    reddits
+    (reddits, failed)
 }
 
-fn parse_song_links_from_file<F>(file: &File, line_preprocess: F) -> Vec<Url>
+fn parse_song_links_from_file<R: Read, F>(file: R, line_preprocess: F) -> Vec<Url>
 where F: Fn(String) -> Option<String> {
     let mut res : Vec<Url> = vec![];
     let file_reader = BufReader::new(file);
@@ -356,53 +732,17 @@ where F: Fn(String) -> Option<String> {
     res
 }
 
-// TODO(nils): error handling
-fn parse_reddit_json(json: &Json) -> Option<RedditEntry> {
-    let json_parser: serde_json::Value = match serde_json::from_str(&json) {
-        Ok(parse) => parse,
-        Err(_) => return None,
-    };
-
-    let pointer = "/0/data/children/0/data";
-    let deref = match json_parser.pointer(pointer) {
-        Some(deref) => deref,
-        None => return None,
-    };
-    let deref: &serde_json::Value = deref;
-
-    let value_to_string = |val: Option<&serde_json::Value>| {
-        val.map(|x| x.clone().as_str().unwrap().to_string())
-    };
-
-    let url_string = value_to_string(deref.get("url"));
-    let url = url_string.map(|u| Url::parse(u.as_str()));
-    let url = url.expect("could not parse json url");
-    let url = url.map(|x| Link(x)).ok();
-
-    let relative_permalink = value_to_string(deref.get("permalink"));
-    let permalink = match relative_permalink {
-        Some(relative_permalink) => {
-            let permalink = String::from("https://www.reddit.com");
-            let permalink = format!("{}{}", permalink, relative_permalink);
-            let permalink_url = Url::parse(permalink.as_str()).ok();
-            permalink_url
-        },
-        None => None,
-    };
-    let permalink = permalink.map(|x| Link(x));
-
-    Some(RedditEntry {
-        url:       url,
-        reddit_id: value_to_string(deref.get("id")),
-        title:     value_to_string(deref.get("title")),
-        subreddit: value_to_string(deref.get("subreddit")),
-        votes:     deref.get("score")       .and_then(|x| x.as_u64()),
-        comments:  deref.get("num_comments").and_then(|x| x.as_u64()),
-        self_link: permalink,
+fn parse_reddit_json(json: &Json) -> Result<RedditEntry, ThreadError> {
+    thing::parse_thread(json).map(|thread| {
+        let mut entry = RedditEntry::from_post(thread.post);
+        entry.comment_tree = comments::comment_tree(&thread.comments, 0);
+        entry
     })
 }
 
-fn get_entries(links: &Vec<Url>, cache: &Cache) -> Vec<Json> {
+/// the cached threads of `links`, posts cached without their comments
+/// only count if the comments are not needed
+fn get_entries(links: &Vec<Url>, cache: &Cache, comments: bool) -> Vec<Json> {
     let mut jsons = Vec::with_capacity(links.len());
     for link in links {
         let key = match id_from_link(&link) {
@@ -410,7 +750,9 @@ fn get_entries(links: &Vec<Url>, cache: &Cache) -> Vec<Json> {
             None => continue,
         };
 
-        match cache.try_to_get(&key) {
+        let json = cache.try_to_get(&key)
+            .or_else(|| if comments { None } else { cache.try_to_get(&info::post_key(&key)) });
+        match json {
             Some(json) => jsons.push(json),
             None => continue,
         };
@@ -418,37 +760,51 @@ fn get_entries(links: &Vec<Url>, cache: &Cache) -> Vec<Json> {
     jsons
 }
 
-fn download_json(link: &Url) -> Option<Json> {
-    // FIXME(nils): error handling
+/// the status, headers and body of the response, error statuses included
+fn download_json(link: &Url) -> Result<Response, DownloadError> {
     let link = match ensure_json_link(link) {
         Some(link) => link,
-        None => return None,
+        None => return Err(DownloadError::InvalidLink(link.to_string())),
     };
     println!("processing {:?}", link);
 
+    let transfer_error = |e: curl::Error| DownloadError::Transfer(e.to_string());
     let mut handle = Easy::new();
     let mut data = Vec::new();
+    let mut headers = Vec::new();
     handle.url(link.as_str())
-        .expect("could not use link");
+        .map_err(|_| DownloadError::InvalidLink(link.to_string()))?;
+    handle.timeout(std::time::Duration::from_secs(60)).map_err(transfer_error)?;
+    handle.follow_location(true).map_err(transfer_error)?;
+    handle.max_redirections(5).map_err(transfer_error)?;
     {
         let mut transfer = handle.transfer();
         transfer.write_function(|new_data| {
             data.extend_from_slice(new_data);
             Ok(new_data.len())
         }).expect("download error");
-        let _ = match transfer.perform() {
-            Err(_) => return None,
-            x => x,
-        };
+        transfer.header_function(|line| {
+            // NB(nils): only keep the headers of the last response after redirects
+            if line.starts_with(b"HTTP/") {
+                headers.clear();
+            }
+            headers.extend(download::parse_header(line));
+            true
+        }).expect("download error");
+        transfer.perform().map_err(transfer_error)?;
     }
 
-    let response = Json::from_utf8(data).expect("could not stringify data");
+    let body = download::response_body(data)?;
 
-    Some(response)
+    Ok(Response {
+        status: handle.response_code().map_err(transfer_error)?,
+        headers,
+        body,
+    })
 }
 
 fn ensure_json_link(link: &Url) -> Option<Url> {
-    match link.as_str().ends_with(".json") {
+    match link.path().ends_with(".json") {
         true =>  Some(link.clone()),
         false => link.join(".json").ok(),
     }
@@ -459,36 +815,136 @@ fn ensure_json_link(link: &Url) -> Option<Url> {
 /// input: links / file
 /// input: cache directory
 fn main() {
-    assert!(false, "write csv header!");
     let program = App::new("Reddit Scrape")
         .arg(Arg::with_name("input")
              .short("i")
              .long("input")
-             .help("input file, either plain text or a [firefox] bookmark file")
+             .help("input files or directories, '-' for stdin: a plain link list, free text or html, markdown, csv, a [firefox] bookmark file, a firefox places.sqlite, a chromium Bookmarks file or a reddit data export (directory or zip)")
              .required(true)
-             .takes_value(true))
+             .takes_value(true)
+             .multiple(true))
+        .arg(Arg::with_name("input-format")
+             .long("input-format")
+             .help("format of all input files, detected from their contents by default")
+             .takes_value(true)
+             .possible_values(&InputFormat::NAMES))
         .arg(Arg::with_name("output")
              .short("o")
              .long("output")
-             .help("output file name - a csv file will be written")
+             .help("output file name, scrape.<format> by default")
              .takes_value(true))
+        .arg(Arg::with_name("format")
+             .short("f")
+             .long("format")
+             .help("output format, entries are written as they are downloaded, \
+                    an existing sqlite database is updated in place")
+             .takes_value(true)
+             .possible_values(&OutputFormat::NAMES)
+             .default_value("csv"))
+        .arg(Arg::with_name("columns")
+             .long("columns")
+             .help("comma separated csv columns in output order, e.g. 'title,url,votes', all fields by default, json output always has all fields")
+             .takes_value(true)
+             .validator(|c| output::parse_columns(&c).map(|_| ())))
+        .arg(Arg::with_name("comments")
+             .long("comments")
+             .help("also write the comments of each thread, nested in json, \
+                    as <output>.comments.csv next to csv and as a table in sqlite"))
+        .arg(Arg::with_name("comment-links")
+             .long("comment-links")
+             .help("also write an entry for every youtube, bandcamp, spotify and soundcloud \
+                    link posted in the comments of a thread"))
+        .arg(Arg::with_name("batch")
+             .long("batch")
+             .help("download only the posts, up to 100 per request, which is much faster \
+                    for large inputs, posts cached this way are downloaded again \
+                    when comments are asked for")
+             .conflicts_with_all(&["comments", "comment-links"]))
+        .arg(Arg::with_name("jobs")
+             .short("j")
+             .long("jobs")
+             .help("downloads in flight at once, all of them share reddit's rate limit")
+             .takes_value(true)
+             .default_value("4")
+             .validator(|n| n.parse::<usize>().map(|_| ()).map_err(|e| e.to_string())))
+        .arg(Arg::with_name("rate")
+             .long("rate")
+             .help("reddit requests per minute, other hosts are limited separately")
+             .takes_value(true)
+             .default_value("20")
+             .validator(|n| n.parse::<u32>().map(|_| ()).map_err(|e| e.to_string())))
+        .arg(Arg::with_name("retries")
+             .long("retries")
+             .help("how often a download is retried after a server error or timeout, \
+                    with exponential backoff")
+             .takes_value(true)
+             .default_value("3")
+             .validator(|n| n.parse::<u32>().map(|_| ()).map_err(|e| e.to_string())))
+        .arg(Arg::with_name("failed")
+             .long("failed")
+             .help("csv file for links that could not be scraped and why, \
+                    <output>.failed.csv by default, can be used as input for a rerun")
+             .takes_value(true))
+        .arg(Arg::with_name("group-by")
+             .long("group-by")
+             .help("how entries are grouped in the html report")
+             .takes_value(true)
+             .possible_values(&GroupBy::NAMES)
+             .default_value("subreddit"))
         .arg(Arg::with_name("cache")
              .short("c")
              .long("cache")
              .help("directory to use as cache, will be read if present and filled with new files")
              .takes_value(true))
+        .arg(Arg::with_name("include-folder")
+             .long("include-folder")
+             .help("only scrape bookmarks in folders matching this glob, e.g. 'musik*'")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1)
+             .validator(|p| glob::Pattern::new(&p).map(|_| ()).map_err(|e| e.to_string())))
+        .arg(Arg::with_name("exclude-folder")
+             .long("exclude-folder")
+             .help("skip bookmarks in folders matching this glob")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1)
+             .validator(|p| glob::Pattern::new(&p).map(|_| ()).map_err(|e| e.to_string())))
+        .arg(Arg::with_name("added-after")
+             .long("added-after")
+             .help("only scrape bookmarks added on or after this date, YYYY-MM-DD or unix timestamp")
+             .takes_value(true)
+             .validator(|d| bookmark::parse_date(&d).map(|_| ())))
+        .arg(Arg::with_name("added-before")
+             .long("added-before")
+             .help("only scrape bookmarks added before this date, YYYY-MM-DD or unix timestamp")
+             .takes_value(true)
+             .validator(|d| bookmark::parse_date(&d).map(|_| ())))
         .arg(Arg::with_name("verbose")
              .short("v")
              .long("verbose")
              .help("verbose output"))
         .get_matches();
 
-    let output_file = program.value_of("output").unwrap_or("scrape.csv");
-    let verbose: bool = program.value_of("verbose").is_some();
-    let input_file = File::open(program.value_of("input").unwrap());
-    let input_file = match input_file {
-        Ok(f) => f,
-        Err(e) => panic!(e),
+    let output_format = program.value_of("format").and_then(OutputFormat::from_name)
+        .unwrap_or(OutputFormat::Csv);
+    let default_output_file = format!("scrape.{}", output_format.extension());
+    let output_file = program.value_of("output").unwrap_or(&default_output_file);
+    let verbose: bool = program.is_present("verbose");
+    let inputs = program.values_of("input").unwrap().collect::<Vec<_>>();
+    let input_format = program.value_of("input-format").and_then(InputFormat::from_name);
+    let bookmarks = collect_song_links(&inputs, input_format, verbose);
+
+    // NB(nils): values have already been checked by the validators
+    let patterns = |name| program.values_of(name)
+        .map(|values| values.filter_map(|p| glob::Pattern::new(p).ok()).collect())
+        .unwrap_or_default();
+    let date = |name| program.value_of(name).and_then(|d| bookmark::parse_date(d).ok());
+    let filter = BookmarkFilter {
+        include: patterns("include-folder"),
+        exclude: patterns("exclude-folder"),
+        added_after: date("added-after"),
+        added_before: date("added-before"),
     };
 
     let mut cache;
@@ -503,7 +959,74 @@ fn main() {
         None => None,
     };
 
-    let reddits = bookmark_to_reddit(&input_file, cache_opt);
+    let options = output::OutputOptions {
+        columns: program.value_of("columns")
+            .map(|c| output::parse_columns(c).expect("columns are validated"))
+            .unwrap_or_else(output::available_columns),
+        group_by: program.value_of("group-by").and_then(GroupBy::from_name)
+            .unwrap_or(GroupBy::Subreddit),
+        comments: program.is_present("comments"),
+    };
+    let mut writer = match output::create_writer(output_format, output_file, options) {
+        Ok(writer) => writer,
+        Err(e) => panic!("could not create {}: {}", output_file, e),
+    };
+
+    let comment_links = program.is_present("comment-links");
+    // NB(nils): values have already been checked by the validators
+    let rate = program.value_of("rate").and_then(|n| n.parse().ok())
+        .unwrap_or(download::REDDIT_PER_MINUTE);
+    let download_options = DownloadOptions {
+        batch: program.is_present("batch"),
+        comments: program.is_present("comments") || comment_links,
+        jobs: program.value_of("jobs").and_then(|n| n.parse().ok()).unwrap_or(4),
+        limiter: download::RateLimiter::new(rate, download::OTHER_PER_MINUTE, 1),
+        retry: download::RetryPolicy {
+            retries: program.value_of("retries").and_then(|n| n.parse().ok()).unwrap_or(3),
+            ..download::RetryPolicy::default()
+        },
+    };
+    let (reddits, failed) = bookmark_to_reddit(bookmarks, &filter, cache_opt, &download_options, |reddit| {
+        if let Err(e) = writer.write(reddit) {
+            println!("could not write {:?} {}", reddit.self_link, e);
+        }
+        if comment_links {
+            for link in reddit.comment_link_entries() {
+                if let Err(e) = writer.write(&link) {
+                    println!("could not write {:?} {}", link.self_link, e);
+                }
+            }
+        }
+    });
+    writer.finish().expect("could not finish output");
+
+    let failed_file = program.value_of("failed").map(String::from)
+        .unwrap_or_else(|| output::failed_path(output_file));
+    if failed.is_empty() {
+        // NB(nils): a default failed file left over from an earlier run would be rerun again,
+        // a file named with --failed is the user's to keep
+        if !program.is_present("failed") {
+            if let Err(e) = output::remove_failed(&failed_file) {
+                println!("could not remove {}: {}", failed_file, e);
+            }
+        }
+    } else {
+        let mut reasons: BTreeMap<&str, usize> = BTreeMap::new();
+        for link in &failed {
+            *reasons.entry(&link.reason).or_default() += 1;
+        }
+        println!("{} of {} links failed, written to {}",
+                 failed.len(), failed.len() + reddits.len(), failed_file);
+        for (reason, count) in reasons {
+            println!("  {:>5} {}", count, reason);
+        }
+        let written = File::create(&failed_file).map_err(|e| e.into())
+            .and_then(|file| download::write_failed_links(file, &failed));
+        if let Err(e) = written {
+            println!("could not write {}: {}", failed_file, e);
+        }
+    }
+
     if verbose {
         for reddit in &reddits {
             match &reddit.url {
@@ -512,11 +1035,6 @@ fn main() {
             };
         }
     }
-
-    let mut writer = csv::Writer::from_path(output_file).unwrap();
-    for reddit in &reddits {
-        writer.serialize(reddit).expect("could not serialize reddit");
-    }
 }
 
 #[cfg(test)]
@@ -532,7 +1050,10 @@ mod test {
         let test_filename = "test_resources/5k0ncr.json";
         let json = load_json_file(test_filename).unwrap();
 
-        let result = parse_reddit_json(&json);
+        let mut result = parse_reddit_json(&json);
+        let comments = result.as_mut().map(|r| r.comment_tree.split_off(0)).unwrap_or_default();
+        assert_eq!(comments.first().and_then(|c| c.parent_id.as_ref()),
+                   Some(&String::from("t3_5k0ncr")));
         let expected = RedditEntry{
             title:     Some(String::from("[Black] Weakling - Dead as Dreams")),
             subreddit: Some(String::from("Metal")),
@@ -541,8 +1062,42 @@ mod test {
             url:       parse("https://www.youtube.com/watch?v=bbvBJMDbyeo"),
             reddit_id: Some(String::from("5k0ncr")),
             self_link: parse("https://www.reddit.com/r/Metal/comments/5k0ncr/black_weakling_dead_as_dreams/"),
+            bookmark_title: None,
+            folder:    None,
+            added:     None,
+            tags:      None,
+            genre:     Some(String::from("Black")),
+            artist:    Some(String::from("Weakling")),
+            track:     Some(String::from("Dead as Dreams")),
+            year:      None,
+            country:   None,
+            ffo:       None,
+            author:    Some(String::from("sakyamuni_lotus777")),
+            created_utc: Some(1482543088),
+            domain:    Some(String::from("youtube.com")),
+            over_18:   Some(false),
+            flair:     None,
+            gilded:    Some(0),
+            upvote_ratio: Some(0.91),
+            is_self:   Some(false),
+            selftext:  None,
+            media_provider: Some(String::from("YouTube")),
+            media_title: Some(String::from("Weakling - Dead as Dreams")),
+            media_author: Some(String::from("Baldersbalet")),
+            media_thumbnail: Some(String::from("https://i.ytimg.com/vi/bbvBJMDbyeo/hqdefault.jpg")),
+            media_html: Some(String::from("<iframe width=\"459\" height=\"344\" src=\"https://www.youtube.com/embed/bbvBJMDbyeo?feature=oembed\" frameborder=\"0\" allowfullscreen></iframe>")),
+            found_in:  None,
+            comment_id: None,
+            comment_author: None,
+            comment_tree: vec![],
         };
-        assert_eq!(result, Some(expected));
+        assert_eq!(result.ok(), Some(expected));
+    }
+
+    #[test]
+    fn test_downvoted_post() {
+        let entry = RedditEntry::from_post(Post { score: Some(-3), ..Post::default() });
+        assert_eq!(entry.votes, Some(-3));
     }
 
     #[test]
@@ -559,15 +1114,60 @@ mod test {
     }
 
     #[test]
-    fn test_bookmark_entry_preprocess() {
-        let mut bookmark_entry = File::open("test_resources/bookmark_entry.txt").expect("could not open entry");
+    fn test_parse_song_links() {
+        let plain = parse_song_links("test_resources/example_links.txt", InputFormat::Plain)
+            .expect("could not read plain links");
+        assert_eq!(plain.len(), 2);
+
+        let detected = InputFormat::detect("test_resources/example_bookmark.html");
+        let bookmarks = parse_song_links("test_resources/example_bookmark.html", detected)
+            .expect("could not read bookmarks");
+        assert_eq!(bookmarks, parse_song_links_from_bookmark(
+            &File::open("test_resources/example_bookmark.html").unwrap()));
+
+        assert!(parse_song_links("test_resources/example_links.txt", InputFormat::Chromium).is_err());
+    }
+
+    #[test]
+    fn test_collect_song_links() {
+        let inputs = ["test_resources/example_links.txt", "test_resources/example_links.md",
+                      "test_resources/does_not_exist"];
+        let bookmarks = collect_song_links(&inputs, None, false);
+        let urls = bookmarks.iter().map(|b| b.url.as_str()).collect::<Vec<_>>();
+
+        // the youtube and spectral lore links are in both files
+        assert_eq!(urls, vec![
+            "https://www.youtube.com/watch?v=o_3jJG_oGSs",
+            "https://www.reddit.com/r/BlackMetal/comments/5elhkp/spectral_lore_cosmic_significance/",
+            "https://www.reddit.com/r/Metal/comments/5k0ncr/black_weakling_dead_as_dreams/",
+        ]);
+    }
+
+    #[test]
+    fn test_collect_song_links_directory() {
+        let files = expand_directory(Path::new("test_resources"));
+        assert!(files.contains(&PathBuf::from("test_resources/reddit_export")));
+        assert!(!files.contains(&PathBuf::from("test_resources/reddit_export/saved_posts.csv")));
+        assert!(files.contains(&PathBuf::from("test_resources/example_bookmark.html")));
+
+        let bookmarks = collect_song_links(&["test_resources"], None, false);
+        let mut urls = bookmarks.iter().map(|b| b.url.clone()).collect::<Vec<_>>();
+        let count = urls.len();
+        urls.sort();
+        urls.dedup();
+        assert_eq!(urls.len(), count);
+        assert!(count >= 413); // unique links in example_bookmark.html
+    }
+
+    #[test]
+    fn test_bookmark_entry() {
+        let bookmark_entry = File::open("test_resources/bookmark_entry.txt").expect("could not open entry");
 
-        let mut entry = String::new();
-        let read_result = bookmark_entry.read_to_string(&mut entry);
-        let result = bookmark_cleanup(entry);
+        let result = parse_song_links_from_bookmark(&bookmark_entry);
 
-        assert!(read_result.is_ok());
-        assert_eq!(result, Some(String::from("https://www.reddit.com/r/Metal/comments/3quxqv/black_zuriaake_%E6%A2%A6%E9%82%80_2015_china_ffo_actual_chinese/")));
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].url.as_str(), "https://www.reddit.com/r/Metal/comments/3quxqv/black_zuriaake_%E6%A2%A6%E9%82%80_2015_china_ffo_actual_chinese/");
+        assert_eq!(result[0].add_date, Some(1447956450));
     }
 
     #[test]
@@ -580,10 +1180,11 @@ mod test {
     }
 
     fn _test_parse_bookmark(input_file: &File) {
-        let mut result = parse_song_links_from_bookmark(&input_file);
-        result.retain(|elem| elem.host_str() == Some("www.reddit.com"));
+        let mut result = parse_song_links_from_bookmark(input_file);
+        result.retain(|elem| elem.url.host_str() == Some("www.reddit.com"));
 
         assert!(result.len() >= 527);
+        assert!(result.iter().all(|elem| !elem.folder.is_empty()));
     }
 
     #[test]
@@ -598,6 +1199,9 @@ mod test {
             .expect("could not parse url");
 
         assert_eq!(ensure_json_link(&url), Some(url));
+
+        let url = info::info_url(&[String::from("5elhkp")]);
+        assert_eq!(ensure_json_link(&url), Some(url));
     }
 
     #[test]
@@ -613,6 +1217,17 @@ mod test {
                              "5elhkp", "spectral_lore_cosmic_significance"]));
     }
 
+    #[test]
+    fn test_id_from_link() {
+        let id = |url: &str| id_from_link(&Url::parse(url).expect("could not parse url"));
+
+        assert_eq!(id("https://www.reddit.com/r/BlackMetal/comments/5elhkp/spectral_lore_cosmic_significance/"),
+                   Some(String::from("5elhkp")));
+        assert_eq!(id("https://redd.it/5elhkp"), Some(String::from("5elhkp")));
+        assert_eq!(id("https://old.reddit.com/r/BlackMetal/comments/5elhkp"), Some(String::from("5elhkp")));
+        assert_eq!(id("http://aelv.se/spill/ul/test_resources/5k0ncr.json"), Some(String::from("test_resources")));
+    }
+
     #[test]
     fn test_filename_from_link() {
         let url = Url::parse("https://www.reddit.com/r/BlackMetal/comments/5elhkp/spectral_lore_cosmic_significance/").unwrap();
@@ -628,19 +1243,22 @@ mod test {
             .expect("could not parse test url");
         let expected = Json::from("{ \"a\" : \"b\" }\n");
 
-        assert_eq!(download_json(&url), Some(expected));
+        let response = download_json(&url).expect("could not download json");
+        assert_eq!(response.status, 200);
+        assert!(response.header("content-type").is_some());
+        assert_eq!(response.body, expected);
     }
 
     #[test]
     fn test_download_and_cache() {
         let url = Url::parse("http://aelv.se/spill/ul/test_resources/5k0ncr.json")
             .expect("could not parse url");
-        let json = download_json(&url).expect("could not download json");
+        let json = download_json(&url).expect("could not download json").body;
         let expected = parse_reddit_json(&json);
 
-        let downloaded = download_reddit_and_cache((&url , &mut None));
-        assert!(downloaded.is_some());
-        assert_eq!(downloaded.map(|x| x.url), expected.map(|x| x.url));
+        let downloaded = cache_and_parse(&url, json.clone(), &mut None);
+        assert!(downloaded.is_ok());
+        assert_eq!(downloaded.ok().map(|x| x.url), expected.ok().map(|x| x.url));
 
         let cache_directory_path = "/tmp/_reddit_scrape_test_cache_empty/";
         let mut cache = Cache::new(&cache_directory_path);
@@ -650,12 +1268,47 @@ mod test {
         assert!(result.is_none());
 
         let expected = parse_reddit_json(&json);
-        let downloaded = download_reddit_and_cache((&url, &mut Some(&mut cache)));
-        assert!(downloaded.is_some());
-        assert_eq!(downloaded.map(|x| x.url), expected.map(|x| x.url));
+        let downloaded = cache_and_parse(&url, json.clone(), &mut Some(&mut cache));
+        assert!(downloaded.is_ok());
+        assert_eq!(downloaded.ok().map(|x| x.url), expected.ok().map(|x| x.url));
         assert!(cache.try_to_get(&key).is_some());
     }
 
+    #[test]
+    fn test_batch_posts_are_not_threads() {
+        let cache_directory_path = PathBuf::from("/tmp/_reddit_scrape_test_cache_batch/");
+        let _ = std::fs::remove_dir_all(&cache_directory_path);
+        let mut cache = Cache::new(&cache_directory_path);
+
+        let json = load_json_file("test_resources/5k0ncr.json").expect("could not load json");
+        let thread: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let info = format!(r#"{{"kind": "Listing", "data": {{"children": [{}]}}}}"#,
+                           thread.pointer("/0/data/children/0").unwrap());
+        let posts = cache_and_parse_batch(info, &mut Some(&mut cache)).expect("could not split listing");
+        assert!(posts["5k0ncr"].is_ok());
+
+        let url = Url::parse("https://www.reddit.com/r/Metal/comments/5k0ncr/black_weakling_dead_as_dreams/")
+            .unwrap();
+        assert_eq!(get_entries(&vec![url.clone()], &cache, false).len(), 1);
+        assert!(get_entries(&vec![url.clone()], &cache, true).is_empty());
+
+        let cache = Cache::load_cache_from_directory(&cache_directory_path).expect("could not load cache");
+        assert!(cache.try_to_get(&String::from("5k0ncr")).is_none());
+        assert!(get_entries(&vec![url], &cache, true).is_empty());
+    }
+
+    #[test]
+    fn test_errors_are_not_cached() {
+        let url = Url::parse("https://www.reddit.com/comments/zzzzzz/").unwrap();
+        let cache_directory_path = PathBuf::from("/tmp/_reddit_scrape_test_cache_errors/");
+        let mut cache = Cache::new(&cache_directory_path);
+
+        let error = Json::from("{\"message\": \"Not Found\", \"error\": 404}");
+        assert!(cache_and_parse(&url, error, &mut Some(&mut cache)).is_err());
+        assert!(cache.try_to_get(&String::from("zzzzzz")).is_none());
+        assert!(!cache_directory_path.join("zzzzzz.json").exists());
+    }
+
     #[test]
     #[allow(non_snake_case)]
     fn test_json_IO() {
@@ -729,7 +1382,7 @@ mod test {
 
         let url = Url::parse("https://www.reddit.com/r/Metal/comments/5k0ncr/black_weakling_dead_as_dreams/")
             .expect("could not parse url");
-        let jsons = get_entries(&vec![url], &mut cache);
+        let jsons = get_entries(&vec![url], &mut cache, false);
 
         // NB(nils): this might fail if the cache does not work
         // NB(nils): and the (updated) json is instead downloaded
@@ -740,7 +1393,11 @@ mod test {
     fn test_bookmark_to_reddit() {
         let bookmark = File::open("test_resources/bookmark_entry.txt")
             .expect("could not read bookmark");
-        let result = bookmark_to_reddit(&bookmark, None);
+        let mut streamed = Vec::new();
+        let (result, failed) = bookmark_to_reddit(parse_song_links_from_bookmark(&bookmark),
+                                        &BookmarkFilter::default(), None,
+                                        &DownloadOptions::default(),
+                                        |reddit| streamed.push(reddit.reddit_id.clone()));
         let expected = RedditEntry {
             url: parse("https://www.youtube.com/watch?v=Jv-HBOA9E0w"),
             reddit_id: Some(String::from("3quxqv")),
@@ -748,38 +1405,72 @@ mod test {
             subreddit: Some(String::from("Metal")),
             votes: Some(24),
             comments: Some(4),
-            self_link: parse("https://www.reddit.com/r/Metal/comments/3quxqv/black_zuriaake_%E6%A2%A6%E9%82%80_2015_china_ffo_actual_chinese/")
+            self_link: parse("https://www.reddit.com/r/Metal/comments/3quxqv/black_zuriaake_%E6%A2%A6%E9%82%80_2015_china_ffo_actual_chinese/"),
+            bookmark_title: Some(String::from("[Black] Zuriaake - 梦邀 (2015, China, FFO: actual Chinese BM, Paysage d\'Hiver, Lunar Aurora) : Metal")),
+            folder:    None,
+            added:     Some(1447956450),
+            tags:      None,
+            genre:     Some(String::from("Black")),
+            artist:    Some(String::from("Zuriaake")),
+            track:     Some(String::from("梦邀")),
+            year:      Some(2015),
+            country:   Some(String::from("China")),
+            ffo:       Some(String::from("actual Chinese BM,Paysage d\'Hiver,Lunar Aurora")),
+            ..RedditEntry::default()
         };
 
         assert!(result.len() == 1);
-        let get_inportant_fields = |x: &RedditEntry| (x.url.clone(), x.self_link.clone()); // reddit fuzzes votes
+        assert!(failed.is_empty());
+        assert_eq!(streamed, vec![result[0].reddit_id.clone()]);
+        let get_inportant_fields = |x: &RedditEntry| (x.url.clone(), x.self_link.clone(),
+                                                      x.bookmark_title.clone(), x.added,
+                                                      x.artist.clone(), x.year, x.ffo.clone()); // reddit fuzzes votes
         assert_eq!(get_inportant_fields(&result[0]),
                    get_inportant_fields(&expected));
     }
 
-    #[test]
-    fn test_throttle() {
-        let mut previous = time::now();
-
-        let func = |_: u32| 1;
-        let tup = throttle(previous, &func, 1); // first call may return imediately
-        previous = tup.1;
-
-        let tup = throttle(previous, &func, 1); // second call must be throttled
-        assert!(tup.1 - previous > time::Duration::seconds(1));
-
-    }
-
     #[test]
     fn test_write_csv() {
         let reddit = RedditEntry::new();
-        let mut writer = csv::WriterBuilder::new()
-            .has_headers(false)
-            .from_writer(vec![]);
-        writer.serialize(reddit).expect("could not serialize reddit to csv");
+        let mut writer = output::CsvOutput::new(vec![], output::available_columns())
+            .expect("could not write csv header");
+        writer.write(&reddit).expect("could not serialize reddit to csv");
         let data = String::from_utf8(writer.into_inner().unwrap()).unwrap();
 
-        assert_eq!(data, "https://www.youtube.com/watch?v=bbvBJMDbyeo,5k0ncr,[Black] Weakling - Dead as Dreams,Metal,83,12,https://www.reddit.com/r/Metal/comments/5k0ncr/black_weakling_dead_as_dreams/\n");
+        assert_eq!(data, "url,reddit_id,title,subreddit,votes,comments,self_link,bookmark_title,folder,added,tags,genre,artist,track,year,country,ffo,\
+                          author,created_utc,domain,over_18,flair,gilded,upvote_ratio,is_self,selftext,\
+                          media_provider,media_title,media_author,media_thumbnail,media_html,found_in,comment_id,comment_author\n\
+                          https://www.youtube.com/watch?v=bbvBJMDbyeo,5k0ncr,[Black] Weakling - Dead as Dreams,Metal,83,12,https://www.reddit.com/r/Metal/comments/5k0ncr/black_weakling_dead_as_dreams/,,,,,Black,Weakling,Dead as Dreams,,,,\
+                          sakyamuni_lotus777,1482543088,youtube.com,false,,0,0.91,false,,\
+                          YouTube,Weakling - Dead as Dreams,Baldersbalet,https://i.ytimg.com/vi/bbvBJMDbyeo/hqdefault.jpg,\
+                          \"<iframe width=\"\"459\"\" height=\"\"344\"\" src=\"\"https://www.youtube.com/embed/bbvBJMDbyeo?feature=oembed\"\" frameborder=\"\"0\"\" allowfullscreen></iframe>\",,,\n");
     }
 
+    #[test]
+    fn test_comment_link_entries() {
+        let json = load_json_file("test_resources/5k0ncr.json").expect("could not load json");
+        let mut reddit = parse_reddit_json(&json).expect("could not parse thread");
+        reddit.folder = Some(String::from("musik 20151119"));
+        reddit.comment_tree = vec![CommentNode {
+            id: Some(String::from("dbk8xyz")),
+            author: Some(String::from("someone")),
+            score: Some(-5),
+            body: Some(String::from("Same as the post https://www.youtube.com/watch?v=bbvBJMDbyeo \
+                                     but try [Wolves in the Throne Room - Diadem of 12 Stars]\
+                                     (https://wittr.bandcamp.com/album/diadem-of-12-stars)")),
+            ..CommentNode::default()
+        }];
+
+        let links = reddit.comment_link_entries();
+        assert_eq!(links.len(), 1);
+        let link = &links[0];
+        assert_eq!(link.url, parse("https://wittr.bandcamp.com/album/diadem-of-12-stars"));
+        assert_eq!(link.artist, Some(String::from("Wolves in the Throne Room")));
+        assert_eq!(link.track, Some(String::from("Diadem of 12 Stars")));
+        assert_eq!((link.votes, link.reddit_id.as_ref()), (Some(-5), None));
+        assert_eq!(link.found_in, Some(String::from("5k0ncr")));
+        assert_eq!(link.comment_author, Some(String::from("someone")));
+        assert_eq!(link.folder, Some(String::from("musik 20151119")));
+        assert_eq!(link.self_link, parse("https://www.reddit.com/comments/5k0ncr/_/dbk8xyz/"));
+    }
 }
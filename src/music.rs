@@ -0,0 +1,275 @@
+//! Music metadata from post titles.
+//!
+//! r/Metal and friends title posts like
+//! "[Black] Zuriaake - 梦邀 (2015, China, FFO: actual Chinese BM, Paysage d'Hiver, Lunar Aurora)":
+//! genres in leading brackets, then artist and track, then a group with
+//! the year, where the band is from and who they sound like ("for fans of").
+//! Titles are written by hand, so everything but the track is optional.
+
+use std::cmp::Reverse;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MusicTitle {
+    pub genres: Vec<String>,
+    pub artist: Option<String>,
+    /// the track or album, whatever is left of the title
+    pub track: String,
+    pub year: Option<u16>,
+    pub country: Option<String>,
+    /// "for fans of" artists
+    pub ffo: Vec<String>,
+}
+
+/// artist and track are split at the first of these
+const SEPARATORS: [&str; 6] = [" - ", " – ", " — ", " | ", "- ", " -"];
+
+fn parse_year(text: &str) -> Option<u16> {
+    let text = text.trim();
+    if text.len() != 4 || !text.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    text.parse().ok().filter(|year| (1900..2100).contains(year))
+}
+
+/// the text after an "FFO" or "for fans of" prefix
+fn strip_ffo(text: &str) -> Option<&str> {
+    let text = text.trim();
+    // NB(nils): ascii lowercasing keeps the byte offsets of the original
+    let lower = text.to_ascii_lowercase();
+    let prefix = ["ffo:", "ffo ", "for fans of:", "for fans of "].iter()
+        .find(|prefix| lower.starts_with(*prefix))?;
+    Some(text[prefix.len()..].trim())
+}
+
+fn split_list(text: &str) -> Vec<String> {
+    text.split(',')
+        .flat_map(|part| part.split(" and "))
+        .map(|part| part.trim().to_string())
+        .filter(|part| !part.is_empty())
+        .collect()
+}
+
+fn is_place(text: &str) -> bool {
+    let words = text.split_whitespace().collect::<Vec<_>>();
+    !words.is_empty() && words.len() <= 3
+        && words.iter().all(|word| word.chars().next().is_some_and(char::is_uppercase))
+}
+
+/// a "(...)" or "[...]" group after the genres,
+/// false if it is part of the track, e.g. "(from The Fall of Hearts)"
+fn parse_group(group: &str, music: &mut MusicTitle) -> bool {
+    let parts = group.split(',').map(str::trim).collect::<Vec<_>>();
+    let ffo_start = parts.iter().position(|part| strip_ffo(part).is_some());
+    let info = &parts[..ffo_start.unwrap_or(parts.len())];
+    let year = info.iter().filter_map(|part| parse_year(part)).next();
+    let places = info.iter().filter(|part| parse_year(part).is_none()).collect::<Vec<_>>();
+
+    let is_info = year.is_some() || ffo_start.is_some() || (places.len() >= 2);
+    if !is_info || !places.iter().all(|place| is_place(place)) {
+        return false;
+    }
+
+    if year.is_some() {
+        music.year = year;
+    }
+    if let Some(country) = places.last() {
+        music.country = Some(country.to_string());
+    }
+    if let Some(start) = ffo_start {
+        let list = [strip_ffo(parts[start]).unwrap_or_default()].iter()
+            .chain(parts[start + 1..].iter())
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(",");
+        music.ffo.extend(split_list(&list));
+    }
+    true
+}
+
+/// the index of the bracket closing the one at the start of `text`
+fn closing(text: &str) -> Option<usize> {
+    let (open, close) = match text.chars().next()? {
+        '(' => ('(', ')'),
+        '[' => ('[', ']'),
+        _ => return None,
+    };
+    let mut depth = 0;
+    for (i, c) in text.char_indices() {
+        if c == open {
+            depth += 1;
+        } else if c == close {
+            depth -= 1;
+            if depth == 0 {
+                return Some(i);
+            }
+        }
+    }
+    None
+}
+
+pub fn parse(title: &str) -> MusicTitle {
+    let mut music = MusicTitle::default();
+    let mut rest = title.trim();
+
+    while rest.starts_with('[') {
+        let end = match closing(rest) {
+            Some(end) => end,
+            None => break,
+        };
+        music.genres.extend(rest[1..end].split('/')
+                            .map(|genre| genre.trim().to_string())
+                            .filter(|genre| !genre.is_empty()));
+        rest = rest[end + 1..].trim_start();
+    }
+
+    // NB(nils): walk the rest, dropping groups that hold metadata
+    // NB(nils): and keeping everything else as the title
+    let mut remaining = String::new();
+    let mut position = 0;
+    while position < rest.len() {
+        let tail = &rest[position..];
+        if let Some(end) = closing(tail) {
+            let group = &tail[1..end];
+            let is_genre = tail.starts_with('[') && strip_ffo(group).is_none()
+                && parse_year(group).is_none();
+            if is_genre {
+                music.genres.extend(group.split('/')
+                                    .map(|genre| genre.trim().to_string())
+                                    .filter(|genre| !genre.is_empty()));
+            } else if !parse_group(group, &mut music) {
+                remaining.push_str(&tail[..=end]);
+            }
+            position += end + 1;
+            continue;
+        }
+        let c = tail.chars().next().expect("position is inside the string");
+        remaining.push(c);
+        position += c.len_utf8();
+    }
+
+    let mut remaining = remaining.split_whitespace().collect::<Vec<_>>().join(" ");
+    let lower = remaining.to_ascii_lowercase();
+    let ffo_start = [", for fans of ", " for fans of ", " ffo:", " ffo "].iter()
+        .filter_map(|prefix| lower.find(prefix))
+        .min();
+    if let Some(start) = ffo_start {
+        let ffo = remaining[start..].trim_start_matches(',').trim().to_string();
+        music.ffo.extend(split_list(strip_ffo(&ffo).unwrap_or_default()));
+        remaining.truncate(start);
+    }
+
+    // NB(nils): the longest separator wins when several start at the same place
+    let separator = SEPARATORS.iter()
+        .filter_map(|separator| remaining.find(separator).map(|split| (split, separator.len())))
+        .min_by_key(|&(split, len)| (split, Reverse(len)));
+    match separator {
+        Some((split, len)) if split > 0 => {
+            music.artist = Some(remaining[..split].trim().to_string());
+            music.track = remaining[split + len..].trim().to_string();
+        },
+        _ => music.track = remaining.trim().to_string(),
+    }
+    music
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn strings(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_full_title() {
+        assert_eq!(parse("[Black] Zuriaake - 梦邀 (2015, China, FFO: actual Chinese BM, Paysage d'Hiver, Lunar Aurora)"),
+                   MusicTitle {
+                       genres: strings(&["Black"]),
+                       artist: Some(String::from("Zuriaake")),
+                       track: String::from("梦邀"),
+                       year: Some(2015),
+                       country: Some(String::from("China")),
+                       ffo: strings(&["actual Chinese BM", "Paysage d'Hiver", "Lunar Aurora"]),
+                   });
+    }
+
+    #[test]
+    fn test_title_variants() {
+        let music = parse("[Black] Weakling - Dead as Dreams");
+        assert_eq!((music.genres, music.artist, music.track),
+                   (strings(&["Black"]), Some(String::from("Weakling")), String::from("Dead as Dreams")));
+
+        let music = parse("[Doom/Gothic][New] Katatonia - Old Heart Falls (from The Fall of Hearts)");
+        assert_eq!(music.genres, strings(&["Doom", "Gothic", "New"]));
+        assert_eq!(music.track, "Old Heart Falls (from The Fall of Hearts)");
+        assert_eq!(music.year, None);
+
+        let music = parse("[Funeral Doom] Lycus - Solar Chamber [FFO: Ahab, Bell Witch]");
+        assert_eq!(music.track, "Solar Chamber");
+        assert_eq!(music.ffo, strings(&["Ahab", "Bell Witch"]));
+
+        let music = parse("[Underground] Vastum - Hole Below [Death Metal] (2015)");
+        assert_eq!(music.genres, strings(&["Underground", "Death Metal"]));
+        assert_eq!((music.track.as_str(), music.year), ("Hole Below", Some(2015)));
+
+        let music = parse("[Technical Melodic Death] Quo Vadis - In Contemp (Quebec, Canada), for fans of Death/Chuck");
+        assert_eq!(music.artist, Some(String::from("Quo Vadis")));
+        assert_eq!(music.track, "In Contemp");
+        assert_eq!(music.country, Some(String::from("Canada")));
+        assert_eq!(music.ffo, strings(&["Death/Chuck"]));
+
+        let music = parse("[NBBMN] Havukruunu (2015) (FFO Immortal and MeloBlack)");
+        assert_eq!((music.artist, music.track.as_str()), (None, "Havukruunu"));
+        assert_eq!(music.ffo, strings(&["Immortal", "MeloBlack"]));
+
+        let music = parse("[Xasthur/Leviathan Split]Xasthur -Telepathic With The Deceased");
+        assert_eq!(music.artist, Some(String::from("Xasthur")));
+        assert_eq!(music.track, "Telepathic With The Deceased");
+
+        let music = parse("[New Release] Obscura- Sermon of the Seven Suns");
+        assert_eq!(music.artist, Some(String::from("Obscura")));
+
+        let music = parse("[Arab Black Metal] Al-Namrood - Hayat Al Khezea (New song)");
+        assert_eq!(music.artist, Some(String::from("Al-Namrood")));
+        assert_eq!(music.track, "Hayat Al Khezea (New song)");
+
+        let music = parse("[Avant Grade Black Metal / Choral Music] DEATHSPELL OMEGA | Carnal Malefactor");
+        assert_eq!(music.genres, strings(&["Avant Grade Black Metal", "Choral Music"]));
+        assert_eq!(music.artist, Some(String::from("DEATHSPELL OMEGA")));
+
+        let music = parse("[DEATHCEMBER] Gotsu Totsu Kotsu (兀突骨) - Harakiri (ハラキリ)");
+        assert_eq!(music.artist, Some(String::from("Gotsu Totsu Kotsu (兀突骨)")));
+        assert_eq!(music.track, "Harakiri (ハラキリ)");
+
+        let music = parse("[Black] İstanbul İblis - Kara Gün ffo Mayhem");
+        assert_eq!(music.artist, Some(String::from("İstanbul İblis")));
+        assert_eq!(music.track, "Kara Gün");
+        assert_eq!(music.ffo, strings(&["Mayhem"]));
+    }
+
+    #[test]
+    fn test_bookmark_titles() {
+        let file = ::std::fs::File::open("test_resources/example_bookmark.html")
+            .expect("could not open bookmarks");
+        let titles = ::bookmark::read_bookmarks(file).into_iter()
+            .filter_map(|bookmark| bookmark.title)
+            .filter(|title| title.starts_with('['))
+            .map(|title| title.trim_end_matches(" : Metal").to_string())
+            .collect::<Vec<_>>();
+        assert!(titles.len() > 100);
+
+        let parsed = titles.iter().map(|title| parse(title)).collect::<Vec<_>>();
+        let with_artist = parsed.iter().filter(|music| music.artist.is_some()).count();
+        assert!(with_artist * 10 > parsed.len() * 8, "{} of {}", with_artist, parsed.len());
+        assert!(parsed.iter().all(|music| !music.genres.is_empty()));
+    }
+
+    #[test]
+    fn test_plain_titles() {
+        assert_eq!(parse("Weakling - Dead as Dreams").artist, Some(String::from("Weakling")));
+        let music = parse("Can you explain the sub-genres to me please?");
+        assert_eq!((music.artist, music.track.as_str()),
+                   (None, "Can you explain the sub-genres to me please?"));
+        assert_eq!(parse(""), MusicTitle::default());
+    }
+}
@@ -0,0 +1,433 @@
+//! Writing scraped entries as csv, json, newline delimited json, playlists,
+//! a html report or into an sqlite database.
+//!
+//! Columns are derived from the serialized `RedditEntry`, so every field
+//! of the struct is available as a column under its field name.
+//! Every writer but the report flushes after each entry, so the output of
+//! a long scrape can be followed while it is running.
+//!
+//! Comments are only written when asked for: nested as `comment_tree` in
+//! json, as a `<name>.comments.csv` next to a csv file and as a `comments`
+//! table in a database.
+
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use csv;
+use serde_json::{self, Value};
+
+use comments;
+use database::DatabaseOutput;
+use playlist::{M3uOutput, XspfOutput};
+use report::{GroupBy, HtmlOutput};
+use RedditEntry;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Csv,
+    Json,
+    Ndjson,
+    Sqlite,
+    M3u8,
+    Xspf,
+    Html,
+}
+
+impl OutputFormat {
+    /// names accepted by `--format`
+    pub const NAMES: [&'static str; 7] = ["csv", "json", "ndjson", "sqlite", "m3u8", "xspf", "html"];
+
+    pub fn from_name(name: &str) -> Option<OutputFormat> {
+        match name {
+            "csv" => Some(OutputFormat::Csv),
+            "json" => Some(OutputFormat::Json),
+            "ndjson" => Some(OutputFormat::Ndjson),
+            "sqlite" => Some(OutputFormat::Sqlite),
+            "m3u8" => Some(OutputFormat::M3u8),
+            "xspf" => Some(OutputFormat::Xspf),
+            "html" => Some(OutputFormat::Html),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match *self {
+            OutputFormat::Csv => "csv",
+            OutputFormat::Json => "json",
+            OutputFormat::Ndjson => "ndjson",
+            OutputFormat::Sqlite => "sqlite",
+            OutputFormat::M3u8 => "m3u8",
+            OutputFormat::Xspf => "xspf",
+            OutputFormat::Html => "html",
+        }
+    }
+}
+
+pub trait EntryWriter {
+    fn write(&mut self, entry: &RedditEntry) -> Result<(), Box<dyn Error>>;
+    /// completes the file, e.g. closes the json array
+    fn finish(self: Box<Self>) -> Result<(), Box<dyn Error>>;
+}
+
+/// settings that only apply to some of the formats
+pub struct OutputOptions {
+    /// csv columns, json always holds the full entry
+    pub columns: Vec<String>,
+    /// sections of the html report
+    pub group_by: GroupBy,
+    /// write the comment tree of each thread
+    pub comments: bool,
+}
+
+/// "scrape.csv" becomes "scrape.comments.csv"
+pub fn comments_path(path: &str) -> String {
+    Path::new(path).with_extension("comments.csv").to_string_lossy().into_owned()
+}
+
+/// "scrape.json" becomes "scrape.failed.csv", see `download::write_failed_links`
+pub fn failed_path(path: &str) -> String {
+    Path::new(path).with_extension("failed.csv").to_string_lossy().into_owned()
+}
+
+/// removes the default failed file of an earlier run, if there is one
+pub fn remove_failed(path: &str) -> io::Result<()> {
+    match ::std::fs::remove_file(path) {
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+/// writers flush after each entry, in between writes are buffered
+fn create(path: &str) -> io::Result<BufWriter<File>> {
+    File::create(path).map(BufWriter::new)
+}
+
+/// files are overwritten, a database is updated
+pub fn create_writer(format: OutputFormat, path: &str, options: OutputOptions)
+    -> Result<Box<dyn EntryWriter>, Box<dyn Error>>
+{
+    let comments = options.comments;
+    Ok(match format {
+        OutputFormat::Csv => {
+            let output = CsvOutput::new(create(path)?, options.columns)?;
+            if comments {
+                Box::new(output.with_comments(create(&comments_path(path))?)?)
+            } else {
+                Box::new(output)
+            }
+        },
+        OutputFormat::Json => Box::new(JsonOutput::new(create(path)?)?.with_comments(comments)),
+        OutputFormat::Ndjson => Box::new(NdjsonOutput::new(create(path)?).with_comments(comments)),
+        OutputFormat::Sqlite => Box::new(DatabaseOutput::open(path)?.with_comments(comments)),
+        OutputFormat::M3u8 => Box::new(M3uOutput::new(create(path)?)?),
+        OutputFormat::Xspf => Box::new(XspfOutput::new(create(path)?)?),
+        OutputFormat::Html => Box::new(HtmlOutput::new(create(path)?, options.group_by)),
+    })
+}
+
+/// the serialized entry, with the nested comments if asked for
+fn entry_json(entry: &RedditEntry, comments: bool) -> Result<Value, serde_json::Error> {
+    let mut value = serde_json::to_value(entry)?;
+    if comments {
+        if let Value::Object(ref mut object) = value {
+            object.insert(String::from("comment_tree"), serde_json::to_value(&entry.comment_tree)?);
+        }
+    }
+    Ok(value)
+}
+
+/// field name and printable value of every field, in declaration order
+fn fields(entry: &RedditEntry) -> Vec<(String, String)> {
+    let value = serde_json::to_value(entry).expect("RedditEntry always serializes");
+    let object = match value {
+        Value::Object(object) => object,
+        _ => unreachable!("RedditEntry serializes as a map"),
+    };
+
+    object.into_iter()
+        .map(|(name, value)| {
+            let value = match value {
+                Value::Null => String::new(),
+                Value::String(s) => s,
+                other => other.to_string(),
+            };
+            (name, value)
+        })
+        .collect()
+}
+
+pub fn available_columns() -> Vec<String> {
+    fields(&RedditEntry::default()).into_iter().map(|(name, _)| name).collect()
+}
+
+/// a comma separated list of column names, e.g. "title,url,votes"
+pub fn parse_columns(spec: &str) -> Result<Vec<String>, String> {
+    let available = available_columns();
+    let columns = spec.split(',')
+        .map(|column| column.trim().to_string())
+        .filter(|column| !column.is_empty())
+        .collect::<Vec<_>>();
+
+    if columns.is_empty() {
+        return Err(String::from("no columns given"));
+    }
+    if let Some(unknown) = columns.iter().find(|column| !available.contains(column)) {
+        return Err(format!("unknown column '{}', available columns are: {}",
+                           unknown, available.join(",")));
+    }
+    Ok(columns)
+}
+
+pub struct CsvOutput<W: Write> {
+    writer: csv::Writer<W>,
+    columns: Vec<String>,
+    comments: Option<csv::Writer<W>>,
+}
+
+impl<W: Write> CsvOutput<W> {
+    /// writes the header row right away, so an empty scrape still
+    /// produces a valid file
+    pub fn new(writer: W, columns: Vec<String>) -> Result<CsvOutput<W>, csv::Error> {
+        let mut writer = csv::WriterBuilder::new()
+            .has_headers(false)
+            .from_writer(writer);
+        writer.write_record(&columns)?;
+        Ok(CsvOutput { writer, columns, comments: None })
+    }
+
+    /// also writes a row for each comment to `writer`
+    pub fn with_comments(mut self, writer: W) -> Result<CsvOutput<W>, csv::Error> {
+        let mut writer = csv::WriterBuilder::new()
+            .has_headers(false)
+            .from_writer(writer);
+        writer.write_record(["reddit_id", "id", "parent_id", "depth", "author",
+                              "score", "created_utc", "body"])?;
+        self.comments = Some(writer);
+        Ok(self)
+    }
+
+    pub fn write(&mut self, entry: &RedditEntry) -> Result<(), csv::Error> {
+        let fields = fields(entry);
+        let record = self.columns.iter().map(|column| {
+            fields.iter()
+                .find(|&(name, _)| name == column)
+                .map_or("", |(_, value)| value.as_str())
+        });
+        self.writer.write_record(record)?;
+        self.writer.flush()?;
+
+        if let (Some(comments), Some(reddit_id)) = (self.comments.as_mut(), entry.reddit_id.as_ref()) {
+            for row in comments::rows(reddit_id, &entry.comment_tree) {
+                comments.serialize(row)?;
+            }
+            comments.flush()?;
+        }
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> Result<W, csv::Error> {
+        if let Some(comments) = self.comments {
+            comments.into_inner().map_err(|e| e.into_error())?;
+        }
+        self.writer.into_inner().map_err(|e| e.into_error().into())
+    }
+}
+
+impl<W: Write> EntryWriter for CsvOutput<W> {
+    fn write(&mut self, entry: &RedditEntry) -> Result<(), Box<dyn Error>> {
+        Ok(CsvOutput::write(self, entry)?)
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), Box<dyn Error>> {
+        self.into_inner()?;
+        Ok(())
+    }
+}
+
+/// a json array, one entry per line
+pub struct JsonOutput<W: Write> {
+    writer: W,
+    empty: bool,
+    comments: bool,
+}
+
+impl<W: Write> JsonOutput<W> {
+    pub fn new(mut writer: W) -> Result<JsonOutput<W>, ::std::io::Error> {
+        writer.write_all(b"[")?;
+        Ok(JsonOutput { writer, empty: true, comments: false })
+    }
+
+    pub fn with_comments(mut self, comments: bool) -> JsonOutput<W> {
+        self.comments = comments;
+        self
+    }
+
+    pub fn into_inner(mut self) -> Result<W, ::std::io::Error> {
+        self.writer.write_all(if self.empty { b"]\n" as &[u8] } else { b"\n]\n" })?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> EntryWriter for JsonOutput<W> {
+    fn write(&mut self, entry: &RedditEntry) -> Result<(), Box<dyn Error>> {
+        self.writer.write_all(if self.empty { b"\n" as &[u8] } else { b",\n" })?;
+        serde_json::to_writer(&mut self.writer, &entry_json(entry, self.comments)?)?;
+        self.writer.flush()?;
+        self.empty = false;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), Box<dyn Error>> {
+        self.into_inner()?;
+        Ok(())
+    }
+}
+
+/// newline delimited json, one entry per line and nothing else
+pub struct NdjsonOutput<W: Write> {
+    writer: W,
+    comments: bool,
+}
+
+impl<W: Write> NdjsonOutput<W> {
+    pub fn new(writer: W) -> NdjsonOutput<W> {
+        NdjsonOutput { writer, comments: false }
+    }
+
+    pub fn with_comments(mut self, comments: bool) -> NdjsonOutput<W> {
+        self.comments = comments;
+        self
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> EntryWriter for NdjsonOutput<W> {
+    fn write(&mut self, entry: &RedditEntry) -> Result<(), Box<dyn Error>> {
+        serde_json::to_writer(&mut self.writer, &entry_json(entry, self.comments)?)?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), Box<dyn Error>> {
+        Ok(self.into_inner().flush()?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_remove_failed() {
+        let path = ::std::env::temp_dir().join(format!("scrape-{}.failed.csv", ::std::process::id()));
+        let path = path.to_str().unwrap();
+        File::create(path).expect("could not create failed file");
+        remove_failed(path).expect("could not remove failed file");
+        assert!(!Path::new(path).exists());
+        remove_failed(path).expect("a missing failed file is not an error");
+    }
+
+    #[test]
+    fn test_available_columns() {
+        let columns = available_columns();
+        assert_eq!(&columns[..7], &["url", "reddit_id", "title", "subreddit",
+                                     "votes", "comments", "self_link"]);
+    }
+
+    #[test]
+    fn test_parse_columns() {
+        assert_eq!(parse_columns("title, url,votes"),
+                   Ok(vec![String::from("title"), String::from("url"), String::from("votes")]));
+        assert!(parse_columns("title,score").is_err());
+        assert!(parse_columns(",").is_err());
+    }
+
+    #[test]
+    fn test_write_json() {
+        let mut output = JsonOutput::new(vec![]).expect("could not start json");
+        output.write(&RedditEntry::new()).expect("could not write entry");
+        output.write(&RedditEntry::default()).expect("could not write entry");
+        let data = String::from_utf8(output.into_inner().unwrap()).unwrap();
+
+        let parsed: Value = serde_json::from_str(&data).expect("output is not valid json");
+        assert_eq!(parsed.as_array().map(|a| a.len()), Some(2));
+        assert_eq!(parsed[0]["reddit_id"], Value::from("5k0ncr"));
+        assert_eq!(parsed[0]["self_link"],
+                   Value::from("https://www.reddit.com/r/Metal/comments/5k0ncr/black_weakling_dead_as_dreams/"));
+        assert_eq!(parsed[1]["votes"], Value::Null);
+
+        let empty = JsonOutput::new(vec![]).and_then(|o| o.into_inner()).unwrap();
+        assert_eq!(String::from_utf8(empty).unwrap(), "[]\n");
+    }
+
+    #[test]
+    fn test_write_json_comments() {
+        let json = ::load_json_file("test_resources/5k0ncr.json").expect("could not load json");
+        let entry = ::parse_reddit_json(&json).expect("could not parse thread");
+
+        let mut output = NdjsonOutput::new(vec![]);
+        output.write(&entry).unwrap();
+        let mut with_comments = NdjsonOutput::new(vec![]).with_comments(true);
+        with_comments.write(&entry).unwrap();
+
+        let without: Value = serde_json::from_slice(&output.into_inner()).unwrap();
+        assert_eq!(without.get("comment_tree"), None);
+        let with: Value = serde_json::from_slice(&with_comments.into_inner()).unwrap();
+        let tree = with["comment_tree"].as_array().expect("no comment tree");
+        assert_eq!(tree.len(), entry.comment_tree.len());
+        assert_eq!(tree[0]["depth"], Value::from(0));
+        assert!(tree[0]["replies"].is_array());
+    }
+
+    #[test]
+    fn test_write_csv_comments() {
+        let json = ::load_json_file("test_resources/5k0ncr.json").expect("could not load json");
+        let entry = ::parse_reddit_json(&json).expect("could not parse thread");
+
+        let mut output = CsvOutput::new(vec![], parse_columns("reddit_id").unwrap()).unwrap()
+            .with_comments(vec![]).unwrap();
+        output.write(&entry).unwrap();
+        let comments = output.comments.take().unwrap().into_inner().ok().unwrap();
+        let comments = String::from_utf8(comments).unwrap();
+
+        let lines = comments.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "reddit_id,id,parent_id,depth,author,score,created_utc,body");
+        assert!(lines[1].starts_with("5k0ncr,"));
+        assert_eq!(::comments::flatten(&entry.comment_tree).len(),
+                   csv::Reader::from_reader(comments.as_bytes()).records().count());
+        assert_eq!(comments_path("out/scrape.csv"), "out/scrape.comments.csv");
+    }
+
+    #[test]
+    fn test_write_ndjson() {
+        let mut output = NdjsonOutput::new(vec![]);
+        output.write(&RedditEntry::new()).expect("could not write entry");
+        output.write(&RedditEntry::new()).expect("could not write entry");
+        let data = String::from_utf8(output.into_inner()).unwrap();
+
+        let lines = data.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        for line in lines {
+            let parsed: Value = serde_json::from_str(line).expect("line is not valid json");
+            assert_eq!(parsed["votes"], Value::from(83));
+        }
+    }
+
+    #[test]
+    fn test_write_selected_columns() {
+        let mut output = CsvOutput::new(vec![], parse_columns("title,url,votes").unwrap())
+            .expect("could not write header");
+        output.write(&RedditEntry::new()).expect("could not write entry");
+        let data = String::from_utf8(output.into_inner().unwrap()).unwrap();
+
+        assert_eq!(data, "title,url,votes\n\
+                          [Black] Weakling - Dead as Dreams,https://www.youtube.com/watch?v=bbvBJMDbyeo,83\n");
+    }
+}
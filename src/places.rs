@@ -0,0 +1,233 @@
+//! Reads bookmarks straight from a firefox profile's `places.sqlite`.
+//!
+//! Firefox keeps the database locked while running, so a copy is made
+//! (together with the write ahead log) and the copy is opened read only.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use rusqlite::{Connection, OpenFlags};
+use url::Url;
+
+use bookmark::Bookmark;
+use text::normalize_title;
+
+const SQLITE_MAGIC: &[u8] = b"SQLite format 3\0";
+const TAGS_ROOT_GUID: &str = "tags________";
+
+// moz_bookmarks.type
+const TYPE_BOOKMARK: i64 = 1;
+const TYPE_FOLDER: i64 = 2;
+
+struct Row {
+    id: i64,
+    kind: i64,
+    parent: i64,
+    title: Option<String>,
+    date_added: Option<i64>,
+    last_modified: Option<i64>,
+    url: Option<String>,
+    place_title: Option<String>,
+    guid: Option<String>,
+}
+
+pub fn is_places_database<P: AsRef<Path>>(path: P) -> bool {
+    let mut magic = [0u8; 16];
+    match File::open(path) {
+        Ok(mut file) => file.read_exact(&mut magic).is_ok() && magic == SQLITE_MAGIC,
+        Err(_) => false,
+    }
+}
+
+/// firefox stores microseconds since the unix epoch
+fn from_prtime(prtime: Option<i64>) -> Option<i64> {
+    prtime.filter(|&t| t > 0).map(|t| t / 1_000_000)
+}
+
+/// the copy of a database, removed together with the `-wal` and `-shm`
+/// files sqlite keeps next to it when dropped
+struct DatabaseCopy(PathBuf);
+
+impl DatabaseCopy {
+    fn with_suffix(&self, suffix: &str) -> PathBuf {
+        let mut path = self.0.as_os_str().to_owned();
+        path.push(suffix);
+        PathBuf::from(path)
+    }
+}
+
+impl Drop for DatabaseCopy {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+        let _ = std::fs::remove_file(self.with_suffix("-wal"));
+        let _ = std::fs::remove_file(self.with_suffix("-shm"));
+    }
+}
+
+fn copy_database(path: &Path) -> Result<DatabaseCopy, Box<dyn Error>> {
+    static COPIES: AtomicUsize = AtomicUsize::new(0);
+    let copy = DatabaseCopy(std::env::temp_dir()
+        .join(format!("_reddit_scrape_places_{}_{}.sqlite", std::process::id(),
+                      COPIES.fetch_add(1, Ordering::SeqCst))));
+    std::fs::copy(path, &copy.0)?;
+
+    let mut wal = path.as_os_str().to_owned();
+    wal.push("-wal");
+    if Path::new(&wal).is_file() {
+        std::fs::copy(&wal, copy.with_suffix("-wal"))?;
+    } else {
+        let _ = std::fs::remove_file(copy.with_suffix("-wal"));
+    }
+
+    Ok(copy)
+}
+
+fn query_rows(connection: &Connection) -> rusqlite::Result<Vec<Row>> {
+    let mut statement = connection.prepare(
+        "SELECT b.id, b.type, b.parent, b.title, b.dateAdded, b.lastModified, \
+                p.url, p.title, b.guid \
+         FROM moz_bookmarks b LEFT JOIN moz_places p ON b.fk = p.id \
+         ORDER BY b.parent, b.position")?;
+    let rows = statement.query_map([], |row| {
+        Ok(Row {
+            id: row.get(0)?,
+            kind: row.get(1)?,
+            parent: row.get(2)?,
+            title: row.get(3)?,
+            date_added: row.get(4)?,
+            last_modified: row.get(5)?,
+            url: row.get(6)?,
+            place_title: row.get(7)?,
+            guid: row.get(8)?,
+        })
+    })?;
+    rows.collect()
+}
+
+/// folder names from below the built in roots ("menu", "toolbar", ...)
+/// down to `id`, the same way a bookmark export nests them
+fn folder_path(id: i64, folders: &HashMap<i64, &Row>) -> Vec<String> {
+    let mut path = Vec::new();
+    let mut current = folders.get(&id);
+    while let Some(folder) = current {
+        let parent = folders.get(&folder.parent);
+        let is_builtin_root = parent.is_none_or(|p| p.parent == 0);
+        if !is_builtin_root {
+            path.push(folder.title.clone().unwrap_or_default());
+        }
+        current = parent;
+    }
+    path.reverse();
+    path
+}
+
+fn rows_to_bookmarks(rows: &[Row]) -> Vec<Bookmark> {
+    let folders = rows.iter()
+        .filter(|row| row.kind == TYPE_FOLDER)
+        .map(|row| (row.id, row))
+        .collect::<HashMap<i64, &Row>>();
+    let tags_root = rows.iter()
+        .find(|row| row.guid.as_deref() == Some(TAGS_ROOT_GUID))
+        .map(|row| row.id);
+    let tag_folder = |row: &Row| folders.get(&row.parent)
+        .filter(|folder| tags_root.is_some() && Some(folder.parent) == tags_root)
+        .cloned();
+
+    // NB(nils): a tag is a folder under the tags root holding
+    // NB(nils): a bookmark entry for each tagged url
+    let mut tags: HashMap<&str, Vec<String>> = HashMap::new();
+    for row in rows.iter().filter(|row| row.kind == TYPE_BOOKMARK) {
+        if let (Some(tag), Some(url)) = (tag_folder(row), row.url.as_ref()) {
+            tags.entry(url.as_str()).or_default()
+                .push(tag.title.clone().unwrap_or_default());
+        }
+    }
+
+    let mut bookmarks = Vec::new();
+    for row in rows.iter().filter(|row| row.kind == TYPE_BOOKMARK) {
+        if tag_folder(row).is_some() {
+            continue;
+        }
+
+        let url = match row.url.as_ref().map(|url| Url::parse(url)) {
+            Some(Ok(url)) => url,
+            _ => continue,
+        };
+        let title = row.title.as_ref().or(row.place_title.as_ref())
+            .map(|title| normalize_title(title))
+            .filter(|title| !title.is_empty());
+
+        bookmarks.push(Bookmark {
+            title,
+            folder: folder_path(row.parent, &folders),
+            add_date: from_prtime(row.date_added),
+            last_modified: from_prtime(row.last_modified),
+            tags: tags.get(url.as_str()).cloned().unwrap_or_default(),
+            url,
+        });
+    }
+
+    bookmarks
+}
+
+pub fn read_places<P: AsRef<Path>>(path: P) -> Result<Vec<Bookmark>, Box<dyn Error>> {
+    // NB(nils): the connection is closed before `copy` is dropped
+    let copy = copy_database(path.as_ref())?;
+    let rows = Connection::open_with_flags(&copy.0, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .and_then(|connection| query_rows(&connection))?;
+
+    Ok(rows_to_bookmarks(&rows))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_places_database() {
+        assert!(is_places_database("test_resources/places.sqlite"));
+        assert!(!is_places_database("test_resources/example_bookmark.html"));
+        assert!(!is_places_database("test_resources/does_not_exist"));
+    }
+
+    #[test]
+    fn test_copy_is_removed() {
+        let copy = copy_database(Path::new("test_resources/places.sqlite"))
+            .expect("could not copy database");
+        let files = [copy.0.clone(), copy.with_suffix("-wal"), copy.with_suffix("-shm")];
+        File::create(&files[2]).expect("could not create shm file");
+        assert!(files[0].is_file());
+
+        drop(copy);
+        assert!(files.iter().all(|file| !file.exists()));
+    }
+
+    #[test]
+    fn test_read_places() {
+        let bookmarks = read_places("test_resources/places.sqlite")
+            .expect("could not read places database");
+        let urls = bookmarks.iter().map(|b| b.url.as_str()).collect::<Vec<_>>();
+
+        assert_eq!(urls, vec![
+            "place:type=6&sort=14&maxResults=10",
+            "https://www.youtube.com/watch?v=bbvBJMDbyeo",
+            "https://www.reddit.com/r/Metal/comments/3quxqv/black_zuriaake_%E6%A2%A6%E9%82%80_2015_china_ffo_actual_chinese/",
+            "https://www.reddit.com/r/BlackMetal/comments/5elhkp/spectral_lore_cosmic_significance/",
+        ]);
+
+        let zuriaake = &bookmarks[2];
+        assert_eq!(zuriaake.folder, vec!["musik 20151119"]);
+        assert_eq!(zuriaake.add_date, Some(1447956450));
+        assert_eq!(zuriaake.tags, vec!["black metal", "china"]);
+        assert_eq!(zuriaake.title, Some(String::from("[Black] Zuriaake - 梦邀 (2015, China, FFO: actual Chinese BM, Paysage d'Hiver, Lunar Aurora) : Metal")));
+
+        assert_eq!(bookmarks[3].folder_path(), "musik 20151119/nested");
+        // untitled bookmarks fall back on the page title
+        assert_eq!(bookmarks[1].title, Some(String::from("Weakling - Dead as Dreams")));
+        assert!(bookmarks[1].folder.is_empty());
+    }
+}
@@ -0,0 +1,152 @@
+//! Playlists of the linked songs, for mpv, vlc and friends.
+//!
+//! The artist and track are parsed from the post title. Entries without a link are left out.
+
+use std::error::Error;
+use std::io::Write;
+
+use music;
+use output::EntryWriter;
+use RedditEntry;
+
+/// artist and track of a "[Genre] Artist - Track (...)" title,
+/// the whole title is the track if there is no artist
+fn artist_and_track(title: &str) -> (Option<String>, String) {
+    let music = music::parse(title);
+    (music.artist, music.track)
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// the extended m3u format, utf-8 encoded
+pub struct M3uOutput<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> M3uOutput<W> {
+    pub fn new(mut writer: W) -> Result<M3uOutput<W>, ::std::io::Error> {
+        writer.write_all(b"#EXTM3U\n")?;
+        Ok(M3uOutput { writer })
+    }
+
+    pub fn into_inner(mut self) -> Result<W, ::std::io::Error> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> EntryWriter for M3uOutput<W> {
+    fn write(&mut self, entry: &RedditEntry) -> Result<(), Box<dyn Error>> {
+        let url = match entry.url {
+            Some(ref url) => url,
+            None => return Ok(()),
+        };
+        if let Some(ref title) = entry.title {
+            // NB(nils): a newline would end the #EXTINF line early
+            let name = match artist_and_track(&title.replace('\n', " ")) {
+                (Some(artist), track) => format!("{} - {}", artist, track),
+                (None, track) => track,
+            };
+            writeln!(self.writer, "#EXTINF:-1,{}", name)?;
+        }
+        writeln!(self.writer, "{}", url)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), Box<dyn Error>> {
+        self.into_inner()?;
+        Ok(())
+    }
+}
+
+/// xml shareable playlist format, the reddit thread is kept as the track's info
+pub struct XspfOutput<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> XspfOutput<W> {
+    pub fn new(mut writer: W) -> Result<XspfOutput<W>, ::std::io::Error> {
+        writer.write_all(b"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                           <playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n  \
+                           <trackList>\n")?;
+        Ok(XspfOutput { writer })
+    }
+
+    pub fn into_inner(mut self) -> Result<W, ::std::io::Error> {
+        self.writer.write_all(b"  </trackList>\n</playlist>\n")?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> EntryWriter for XspfOutput<W> {
+    fn write(&mut self, entry: &RedditEntry) -> Result<(), Box<dyn Error>> {
+        let url = match entry.url {
+            Some(ref url) => url,
+            None => return Ok(()),
+        };
+        writeln!(self.writer, "    <track>")?;
+        writeln!(self.writer, "      <location>{}</location>", escape_xml(&url.to_string()))?;
+        if let Some(ref title) = entry.title {
+            let (artist, track) = artist_and_track(title);
+            writeln!(self.writer, "      <title>{}</title>", escape_xml(&track))?;
+            if let Some(artist) = artist {
+                writeln!(self.writer, "      <creator>{}</creator>", escape_xml(&artist))?;
+            }
+        }
+        if let Some(ref self_link) = entry.self_link {
+            writeln!(self.writer, "      <info>{}</info>", escape_xml(&self_link.to_string()))?;
+        }
+        if let Some(ref thumbnail) = entry.media_thumbnail {
+            writeln!(self.writer, "      <image>{}</image>", escape_xml(thumbnail))?;
+        }
+        writeln!(self.writer, "    </track>")?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), Box<dyn Error>> {
+        self.into_inner()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_write_m3u() {
+        let mut output = M3uOutput::new(vec![]).unwrap();
+        output.write(&RedditEntry::new()).expect("could not write entry");
+        output.write(&RedditEntry::default()).expect("could not write entry");
+        let data = String::from_utf8(output.into_inner().unwrap()).unwrap();
+
+        assert_eq!(data, "#EXTM3U\n\
+                          #EXTINF:-1,Weakling - Dead as Dreams\n\
+                          https://www.youtube.com/watch?v=bbvBJMDbyeo\n");
+    }
+
+    #[test]
+    fn test_write_xspf() {
+        let mut entry = RedditEntry::new();
+        entry.title = Some(String::from("[Black] Weakling & co - <Dead> as Dreams"));
+        let mut output = XspfOutput::new(vec![]).unwrap();
+        output.write(&entry).expect("could not write entry");
+        let data = String::from_utf8(output.into_inner().unwrap()).unwrap();
+
+        assert!(data.starts_with("<?xml"));
+        assert!(data.contains("<location>https://www.youtube.com/watch?v=bbvBJMDbyeo</location>"));
+        assert!(data.contains("<title>&lt;Dead&gt; as Dreams</title>"));
+        assert!(data.contains("<creator>Weakling &amp; co</creator>"));
+        assert!(data.contains("<image>https://i.ytimg.com/vi/bbvBJMDbyeo/hqdefault.jpg</image>"));
+        assert!(data.ends_with("</trackList>\n</playlist>\n"));
+    }
+}
@@ -0,0 +1,90 @@
+//! The `data` of a reddit post (a `t3` thing) as returned by the json api.
+//!
+//! Only the fields worth keeping are listed, reddit adds and removes
+//! fields over time so every one of them is optional.
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Post {
+    pub id: Option<String>,
+    pub title: Option<String>,
+    pub subreddit: Option<String>,
+    /// the linked page, or the post itself for self posts
+    pub url: Option<String>,
+    /// relative to https://www.reddit.com
+    pub permalink: Option<String>,
+    pub score: Option<i64>,
+    pub num_comments: Option<u64>,
+    pub author: Option<String>,
+    /// seconds since the unix epoch, reddit sends it as a float
+    pub created_utc: Option<f64>,
+    pub domain: Option<String>,
+    pub over_18: Option<bool>,
+    pub link_flair_text: Option<String>,
+    pub gilded: Option<u64>,
+    pub upvote_ratio: Option<f64>,
+    /// html escaped markdown
+    pub selftext: Option<String>,
+    pub is_self: Option<bool>,
+    pub media: Option<Media>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Media {
+    pub oembed: Option<Oembed>,
+}
+
+/// what the linked site (youtube, bandcamp, ...) says about the media
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Oembed {
+    pub provider_name: Option<String>,
+    pub title: Option<String>,
+    pub author_name: Option<String>,
+    pub thumbnail_url: Option<String>,
+    /// html escaped embed code, usually an iframe
+    pub html: Option<String>,
+}
+
+impl Post {
+    pub fn oembed(&self) -> Option<&Oembed> {
+        self.media.as_ref().and_then(|media| media.oembed.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::{self, Value};
+
+    #[test]
+    fn test_deserialize_post() {
+        let file = ::std::fs::File::open("test_resources/5k0ncr.json").expect("could not open json");
+        let json: Value = serde_json::from_reader(file).expect("could not parse json");
+        let data = json.pointer("/0/data/children/0/data").expect("no post in listing");
+        let post: Post = serde_json::from_value(data.clone()).expect("could not deserialize post");
+
+        assert_eq!(post.id, Some(String::from("5k0ncr")));
+        assert_eq!(post.author, Some(String::from("sakyamuni_lotus777")));
+        assert_eq!(post.created_utc, Some(1482543088.0));
+        assert_eq!(post.domain, Some(String::from("youtube.com")));
+        assert_eq!((post.over_18, post.is_self, post.gilded), (Some(false), Some(false), Some(0)));
+        assert_eq!(post.link_flair_text, None);
+        assert_eq!(post.upvote_ratio, Some(0.91));
+        assert_eq!(post.selftext, Some(String::new()));
+
+        let oembed = post.oembed().expect("no oembed");
+        assert_eq!(oembed.provider_name, Some(String::from("YouTube")));
+        assert_eq!(oembed.author_name, Some(String::from("Baldersbalet")));
+        assert!(oembed.html.as_ref().is_some_and(|html| html.starts_with("&lt;iframe")));
+    }
+
+    #[test]
+    fn test_missing_fields() {
+        let post: Post = serde_json::from_str(r#"{"id": "abc", "media": null, "unknown": 1}"#)
+            .expect("could not deserialize post");
+        assert_eq!(post.id, Some(String::from("abc")));
+        assert_eq!(post.oembed(), None);
+    }
+}
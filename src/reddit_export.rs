@@ -0,0 +1,171 @@
+//! Reads the saved posts and comments from reddit's account data export,
+//! either the downloaded zip file or the directory it was extracted to.
+//!
+//! Saved comments are scraped as the thread they were posted in.
+
+use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use csv;
+use url::Url;
+use zip::ZipArchive;
+
+use bookmark::Bookmark;
+
+/// the export files that list saved things, also used as the bookmark folder
+const SAVED_FILES: [&str; 2] = ["saved_posts.csv", "saved_comments.csv"];
+const SAVED_POSTS: &str = "saved_posts.csv";
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+#[derive(Debug, Deserialize)]
+struct SavedRow {
+    id: String,
+    #[serde(default)]
+    permalink: Option<String>,
+}
+
+pub fn is_reddit_export<P: AsRef<Path>>(path: P) -> bool {
+    let path = path.as_ref();
+    if path.is_dir() {
+        return SAVED_FILES.iter().any(|name| path.join(name).is_file());
+    }
+
+    let mut magic = [0u8; 4];
+    match File::open(path) {
+        Ok(mut file) => file.read_exact(&mut magic).is_ok() && magic == ZIP_MAGIC,
+        Err(_) => false,
+    }
+}
+
+/// the thread a saved post or comment belongs to,
+/// comment permalinks are cut off after the thread's slug,
+/// without a permalink only a post's thread is known from its id
+fn thread_link(row: &SavedRow, is_post: bool) -> Option<Url> {
+    let permalink = row.permalink.as_ref()
+        .filter(|p| !p.trim().is_empty())
+        .and_then(|p| Url::parse(p.trim()).ok());
+    let mut permalink = match permalink {
+        Some(permalink) => permalink,
+        None if is_post => return Url::parse(&format!("https://www.reddit.com/comments/{}/",
+                                                      row.id.trim())).ok(),
+        None => return None,
+    };
+
+    let segments = permalink.path_segments()
+        .map(|s| s.filter(|s| !s.is_empty()).map(String::from).collect::<Vec<_>>())
+        .unwrap_or_default();
+    let thread = segments.iter().position(|s| s == "comments")
+        .filter(|&i| segments.len() >= i + 3)
+        .map(|i| &segments[..i + 3]);
+    if let Some(thread) = thread {
+        permalink.set_path(&format!("/{}/", thread.join("/")));
+        permalink.set_query(None);
+    }
+    Some(permalink)
+}
+
+fn read_saved<R: Read>(reader: R, name: &str) -> Result<Vec<Bookmark>, csv::Error> {
+    let mut bookmarks = Vec::new();
+    let mut skipped = 0;
+    for row in csv::Reader::from_reader(reader).deserialize() {
+        let row: SavedRow = row?;
+        match thread_link(&row, name == SAVED_POSTS) {
+            Some(url) => {
+                let mut bookmark = Bookmark::from_url(url);
+                bookmark.folder = vec![name.trim_end_matches(".csv").to_string()];
+                bookmarks.push(bookmark);
+            },
+            None => skipped += 1,
+        }
+    }
+    if skipped > 0 {
+        println!("skipped {} rows of {} without a usable permalink", skipped, name);
+    }
+    Ok(bookmarks)
+}
+
+/// the export directory or zip file,
+/// missing saved_*.csv files are treated as empty
+pub fn read_reddit_export<P: AsRef<Path>>(path: P) -> Result<Vec<Bookmark>, Box<dyn Error>> {
+    let path = path.as_ref();
+    let mut bookmarks = Vec::new();
+
+    if path.is_dir() {
+        for name in SAVED_FILES.iter() {
+            let file = match File::open(path.join(name)) {
+                Ok(file) => file,
+                Err(_) => continue,
+            };
+            bookmarks.extend(read_saved(file, name)?);
+        }
+        return Ok(bookmarks);
+    }
+
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    for name in SAVED_FILES.iter() {
+        // NB(nils): the files may be in a subdirectory of the archive
+        let entry = archive.file_names()
+            .find(|entry| *entry == *name || entry.ends_with(&format!("/{}", name)))
+            .map(String::from);
+        if let Some(entry) = entry {
+            bookmarks.extend(read_saved(archive.by_name(&entry)?, name)?);
+        }
+    }
+    Ok(bookmarks)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn expected_urls() -> Vec<&'static str> {
+        vec![
+            "https://www.reddit.com/r/Metal/comments/5k0ncr/black_weakling_dead_as_dreams/",
+            "https://www.reddit.com/r/BlackMetal/comments/5elhkp/spectral_lore_cosmic_significance/",
+            "https://www.reddit.com/comments/3quxqv/",
+            "https://www.reddit.com/r/Metal/comments/5k0ncr/black_weakling_dead_as_dreams/",
+        ]
+    }
+
+    #[test]
+    fn test_is_reddit_export() {
+        assert!(is_reddit_export("test_resources/reddit_export"));
+        assert!(is_reddit_export("test_resources/reddit_export.zip"));
+        assert!(!is_reddit_export("test_resources"));
+        assert!(!is_reddit_export("test_resources/example_bookmark.html"));
+    }
+
+    #[test]
+    fn test_read_export_directory() {
+        let bookmarks = read_reddit_export("test_resources/reddit_export")
+            .expect("could not read export");
+
+        assert_eq!(bookmarks.iter().map(|b| b.url.as_str()).collect::<Vec<_>>(), expected_urls());
+        assert_eq!(bookmarks[0].folder, vec!["saved_posts"]);
+        assert_eq!(bookmarks[3].folder, vec!["saved_comments"]);
+
+        let ids = bookmarks.iter().filter_map(|b| ::id_from_link(&b.url)).collect::<Vec<_>>();
+        assert_eq!(ids, vec!["5k0ncr", "5elhkp", "3quxqv", "5k0ncr"]);
+    }
+
+    #[test]
+    fn test_comments_without_permalink() {
+        let csv = "id,permalink\n\
+                   dbkrbf3,\n\
+                   dbkrbf4,https://www.reddit.com/r/Metal/comments/5k0ncr/black_weakling_dead_as_dreams/dbkrbf4/\n";
+        let bookmarks = read_saved(csv.as_bytes(), "saved_comments.csv").expect("could not read csv");
+
+        assert_eq!(bookmarks.iter().map(|b| b.url.as_str()).collect::<Vec<_>>(),
+                   vec!["https://www.reddit.com/r/Metal/comments/5k0ncr/black_weakling_dead_as_dreams/"]);
+    }
+
+    #[test]
+    fn test_read_export_zip() {
+        let bookmarks = read_reddit_export("test_resources/reddit_export.zip")
+            .expect("could not read export");
+
+        assert_eq!(bookmarks.iter().map(|b| b.url.as_str()).collect::<Vec<_>>(), expected_urls());
+    }
+}
@@ -0,0 +1,143 @@
+//! Normalization of the many ways to link to a reddit thread.
+//!
+//! `old.`, `np.`, `m.` and plain `reddit.com` hosts, `redd.it` short links,
+//! `/comments/<id>` links without subreddit or slug, comment permalinks and
+//! `.json` links all map to the same thread id and canonical url.
+
+use url::Url;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RedditLink {
+    /// reddit's base36 thread id, without the `t3_` prefix
+    pub id: String,
+    pub subreddit: Option<String>,
+    pub slug: Option<String>,
+}
+
+fn is_reddit_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+fn is_reddit_host(host: &str) -> bool {
+    host == "reddit.com" || host.ends_with(".reddit.com")
+}
+
+impl RedditLink {
+    /// None for anything that is not a link to a reddit thread
+    pub fn parse(url: &Url) -> Option<RedditLink> {
+        if url.scheme() != "http" && url.scheme() != "https" {
+            return None;
+        }
+        let host = url.host_str()?.to_lowercase();
+
+        let mut segments = url.path_segments()?
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>();
+        if let Some(last) = segments.pop() {
+            let last = last.trim_end_matches(".json");
+            if !last.is_empty() {
+                segments.push(last);
+            }
+        }
+
+        if host == "redd.it" || host == "www.redd.it" {
+            return segments.first()
+                .filter(|id| segments.len() == 1 && is_reddit_id(id))
+                .map(|id| RedditLink { id: id.to_lowercase(), subreddit: None, slug: None });
+        }
+        if !is_reddit_host(&host) {
+            return None;
+        }
+
+        let position = segments.iter().position(|&s| s == "comments" || s == "gallery")?;
+        let id = segments.get(position + 1).filter(|id| is_reddit_id(id))?;
+        let subreddit = if position >= 2 && segments[position - 2] == "r" {
+            Some(segments[position - 1].to_string())
+        } else {
+            None
+        };
+        let slug = if segments[position] == "comments" {
+            segments.get(position + 2).map(|s| s.to_string())
+        } else {
+            None
+        };
+
+        Some(RedditLink { id: id.to_lowercase(), subreddit, slug })
+    }
+
+    /// the one url used to compare, cache and download threads
+    pub fn canonical(&self) -> Url {
+        Url::parse(&format!("https://www.reddit.com/comments/{}/", self.id))
+            .expect("reddit ids are url safe")
+    }
+}
+
+/// the canonical url for reddit threads, other links as they are
+pub fn normalize(url: &Url) -> Url {
+    match RedditLink::parse(url) {
+        Some(link) => link.canonical(),
+        None => url.clone(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(url: &str) -> Option<RedditLink> {
+        RedditLink::parse(&Url::parse(url).expect("could not parse url"))
+    }
+
+    #[test]
+    fn test_thread_variants() {
+        let variants = [
+            "https://www.reddit.com/r/Metal/comments/5k0ncr/black_weakling_dead_as_dreams/",
+            "https://www.reddit.com/r/Metal/comments/5k0ncr/black_weakling_dead_as_dreams",
+            "http://reddit.com/r/Metal/comments/5k0ncr/black_weakling_dead_as_dreams/",
+            "https://old.reddit.com/r/Metal/comments/5k0ncr/black_weakling_dead_as_dreams/?sort=top",
+            "https://np.reddit.com/r/Metal/comments/5k0ncr/",
+            "https://m.reddit.com/r/Metal/comments/5k0ncr",
+            "https://www.reddit.com/comments/5k0ncr",
+            "https://redd.it/5k0ncr",
+            "https://www.reddit.com/r/Metal/comments/5k0ncr/black_weakling_dead_as_dreams/dbkrbf3/",
+            "https://www.reddit.com/r/Metal/comments/5k0ncr/black_weakling_dead_as_dreams/.json",
+            "https://www.reddit.com/r/Metal/comments/5k0ncr.json",
+        ];
+        let expected = Url::parse("https://www.reddit.com/comments/5k0ncr/").unwrap();
+
+        for variant in variants.iter() {
+            let link = parse(variant);
+            assert_eq!(link.as_ref().map(|l| l.id.as_str()), Some("5k0ncr"), "{}", variant);
+            assert_eq!(link.map(|l| l.canonical()), Some(expected.clone()), "{}", variant);
+        }
+    }
+
+    #[test]
+    fn test_link_parts() {
+        assert_eq!(parse("https://old.reddit.com/r/BlackMetal/comments/5elhkp/spectral_lore_cosmic_significance/"),
+                   Some(RedditLink {
+                       id: String::from("5elhkp"),
+                       subreddit: Some(String::from("BlackMetal")),
+                       slug: Some(String::from("spectral_lore_cosmic_significance")),
+                   }));
+        assert_eq!(parse("https://redd.it/5elhkp").and_then(|l| l.subreddit), None);
+    }
+
+    #[test]
+    fn test_not_threads() {
+        assert_eq!(parse("https://www.reddit.com/r/Metal/"), None);
+        assert_eq!(parse("https://www.reddit.com/"), None);
+        assert_eq!(parse("https://www.youtube.com/watch?v=bbvBJMDbyeo"), None);
+        assert_eq!(parse("https://notreddit.com/r/Metal/comments/5k0ncr/"), None);
+        assert_eq!(parse("https://redd.it/"), None);
+        assert_eq!(parse("http://aelv.se/spill/ul/test_resources/5k0ncr.json"), None);
+    }
+
+    #[test]
+    fn test_normalize() {
+        let youtube = Url::parse("https://www.youtube.com/watch?v=bbvBJMDbyeo").unwrap();
+        assert_eq!(normalize(&youtube), youtube);
+        assert_eq!(normalize(&Url::parse("https://redd.it/5k0ncr").unwrap()).as_str(),
+                   "https://www.reddit.com/comments/5k0ncr/");
+    }
+}
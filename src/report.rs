@@ -0,0 +1,198 @@
+//! A self contained html page listing the scraped entries,
+//! grouped by subreddit or bookmark folder.
+//!
+//! Grouping needs every entry, so they are kept until `finish`.
+//! Each group is a table that sorts by a column when its header is clicked.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::io::Write;
+
+use output::EntryWriter;
+use RedditEntry;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupBy {
+    Subreddit,
+    Folder,
+}
+
+impl GroupBy {
+    /// names accepted by `--group-by`
+    pub const NAMES: [&'static str; 2] = ["subreddit", "folder"];
+
+    pub fn from_name(name: &str) -> Option<GroupBy> {
+        match name {
+            "subreddit" => Some(GroupBy::Subreddit),
+            "folder" => Some(GroupBy::Folder),
+            _ => None,
+        }
+    }
+
+    fn group(&self, entry: &RedditEntry) -> String {
+        let group = match *self {
+            GroupBy::Subreddit => entry.subreddit.as_ref().map(|s| format!("r/{}", s)),
+            GroupBy::Folder => entry.folder.clone(),
+        };
+        group.unwrap_or_else(|| String::from("(none)"))
+    }
+}
+
+const HEAD: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>scraped reddit entries</title>
+<style>
+body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; margin-bottom: 2em; }
+th, td { padding: 0.2em 0.6em; text-align: left; }
+th { cursor: pointer; border-bottom: 1px solid #888; }
+tr:nth-child(even) td { background: #f0f0f0; }
+td.number { text-align: right; }
+</style>
+</head>
+<body>
+"#;
+
+const TAIL: &str = r#"<script>
+document.querySelectorAll("th").forEach(function (th) {
+    th.addEventListener("click", function () {
+        var table = th.closest("table");
+        var body = table.tBodies[0];
+        var column = th.cellIndex;
+        var ascending = th.dataset.order !== "asc";
+        th.dataset.order = ascending ? "asc" : "desc";
+        var key = function (row) {
+            var cell = row.cells[column];
+            return cell.dataset.sort !== undefined ? Number(cell.dataset.sort) : cell.textContent.toLowerCase();
+        };
+        Array.from(body.rows).sort(function (a, b) {
+            var x = key(a), y = key(b);
+            return (x < y ? -1 : x > y ? 1 : 0) * (ascending ? 1 : -1);
+        }).forEach(function (row) { body.appendChild(row); });
+    });
+});
+</script>
+</body>
+</html>
+"#;
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn link(href: Option<String>, text: &str) -> String {
+    match href {
+        Some(href) => format!("<a href=\"{}\">{}</a>", escape_html(&href), escape_html(text)),
+        None => String::new(),
+    }
+}
+
+fn number<T: fmt::Display>(value: Option<T>) -> String {
+    match value {
+        Some(value) => format!("<td class=\"number\" data-sort=\"{0}\">{0}</td>", value),
+        None => String::from("<td class=\"number\" data-sort=\"-Infinity\"></td>"),
+    }
+}
+
+pub struct HtmlOutput<W: Write> {
+    writer: W,
+    group_by: GroupBy,
+    entries: Vec<RedditEntry>,
+}
+
+impl<W: Write> HtmlOutput<W> {
+    pub fn new(writer: W, group_by: GroupBy) -> HtmlOutput<W> {
+        HtmlOutput { writer, group_by, entries: Vec::new() }
+    }
+
+    /// writes the whole page
+    pub fn into_inner(mut self) -> Result<W, ::std::io::Error> {
+        let mut groups: BTreeMap<String, Vec<&RedditEntry>> = BTreeMap::new();
+        for entry in &self.entries {
+            groups.entry(self.group_by.group(entry)).or_default().push(entry);
+        }
+
+        self.writer.write_all(HEAD.as_bytes())?;
+        writeln!(self.writer, "<h1>{} entries</h1>", self.entries.len())?;
+        for (group, entries) in groups {
+            writeln!(self.writer, "<h2>{} ({})</h2>", escape_html(&group), entries.len())?;
+            writeln!(self.writer, "<table>\n<thead><tr><th>title</th><th>author</th>\
+                                   <th>votes</th><th>comments</th><th>link</th></tr></thead>\n<tbody>")?;
+            for entry in entries {
+                let title = entry.title.as_ref().map_or("", |t| t.as_str());
+                writeln!(self.writer, "<tr><td>{}</td><td>{}</td>{}{}<td>{}</td></tr>",
+                         link(entry.self_link.as_ref().map(|l| l.to_string()), title),
+                         escape_html(entry.author.as_ref().map_or("", |a| a.as_str())),
+                         number(entry.votes),
+                         number(entry.comments),
+                         entry.url.as_ref().map(|l| l.to_string())
+                             .map(|url| link(Some(url.clone()), &url))
+                             .unwrap_or_default())?;
+            }
+            writeln!(self.writer, "</tbody>\n</table>")?;
+        }
+        self.writer.write_all(TAIL.as_bytes())?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> EntryWriter for HtmlOutput<W> {
+    fn write(&mut self, entry: &RedditEntry) -> Result<(), Box<dyn Error>> {
+        self.entries.push(entry.clone());
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), Box<dyn Error>> {
+        self.into_inner()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn report(group_by: GroupBy, entries: &[RedditEntry]) -> String {
+        let mut output = HtmlOutput::new(vec![], group_by);
+        for entry in entries {
+            output.write(entry).expect("could not write entry");
+        }
+        String::from_utf8(output.into_inner().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_report_by_subreddit() {
+        let mut other = RedditEntry::new();
+        other.subreddit = Some(String::from("BlackMetal"));
+        other.title = Some(String::from("<script>"));
+        other.votes = None;
+        let html = report(GroupBy::Subreddit, &[RedditEntry::new(), other]);
+
+        assert!(html.contains("<h1>2 entries</h1>"));
+        let black_metal = html.find("<h2>r/BlackMetal (1)</h2>").expect("no BlackMetal group");
+        let metal = html.find("<h2>r/Metal (1)</h2>").expect("no Metal group");
+        assert!(black_metal < metal);
+        assert!(html.contains("<a href=\"https://www.reddit.com/r/Metal/comments/5k0ncr/black_weakling_dead_as_dreams/\">\
+                               [Black] Weakling - Dead as Dreams</a>"));
+        assert!(html.contains("<td>sakyamuni_lotus777</td><td class=\"number\" data-sort=\"83\">83</td>"));
+        assert!(html.contains("&lt;script&gt;"));
+        assert!(html.ends_with("</html>\n"));
+    }
+
+    #[test]
+    fn test_report_by_folder() {
+        let mut entry = RedditEntry::new();
+        entry.folder = Some(String::from("musik 20151119"));
+        let html = report(GroupBy::Folder, &[entry, RedditEntry::new()]);
+
+        assert!(html.contains("<h2>musik 20151119 (1)</h2>"));
+        assert!(html.contains("<h2>(none) (1)</h2>"));
+    }
+}
@@ -0,0 +1,106 @@
+//! Cleaning up titles from the different sources.
+//!
+//! Bookmark exports and reddit's json escape titles as html, and the same
+//! title may arrive composed or decomposed ("é" or "e" + "◌́"), so titles
+//! are entity decoded and NFC normalized before they are compared or written.
+
+use unicode_normalization::UnicodeNormalization;
+
+/// named entities seen in titles, anything else is kept as written
+const NAMED_ENTITIES: [(&str, char); 18] = [
+    ("amp", '&'),
+    ("lt", '<'),
+    ("gt", '>'),
+    ("quot", '"'),
+    ("apos", '\''),
+    ("nbsp", '\u{a0}'),
+    ("ndash", '–'),
+    ("mdash", '—'),
+    ("hellip", '…'),
+    ("lsquo", '‘'),
+    ("rsquo", '’'),
+    ("ldquo", '“'),
+    ("rdquo", '”'),
+    ("laquo", '«'),
+    ("raquo", '»'),
+    ("copy", '©'),
+    ("reg", '®'),
+    ("trade", '™'),
+];
+
+/// the character of an entity without its `&` and `;`
+fn entity(name: &str) -> Option<char> {
+    if let Some(number) = name.strip_prefix('#') {
+        let code = match number.strip_prefix('x').or_else(|| number.strip_prefix('X')) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+        return ::std::char::from_u32(code).filter(|&c| c != '\0');
+    }
+    NAMED_ENTITIES.iter().find(|&&(n, _)| n == name).map(|&(_, c)| c)
+}
+
+/// resolves named and numeric html entities, a single pass so
+/// "&amp;lt;" becomes "&lt;" and not "<"
+pub fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        // NB(nils): entity names are short, a far away ';' is just text
+        let c = rest.find(';')
+            .filter(|&end| end <= 10)
+            .and_then(|end| entity(&rest[1..end]).map(|c| (c, end)));
+        match c {
+            Some((c, end)) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            },
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            },
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// decoded, NFC normalized and trimmed
+pub fn normalize_title(title: &str) -> String {
+    decode_entities(title).nfc().collect::<String>().trim().to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_decode_entities() {
+        assert_eq!(decode_entities("Chthe&#39;ilist - Vecoiitn’aphnaat’smaala"),
+                   "Chthe'ilist - Vecoiitn’aphnaat’smaala");
+        assert_eq!(decode_entities("new album &quot;King&quot; &amp; more"),
+                   "new album \"King\" & more");
+        assert_eq!(decode_entities("&lt;iframe src=&quot;x&quot;&gt;"), "<iframe src=\"x\">");
+        assert_eq!(decode_entities("&#x68A6;&#36992;"), "梦邀");
+        assert_eq!(decode_entities("&amp;lt;"), "&lt;");
+    }
+
+    #[test]
+    fn test_decode_leaves_text() {
+        assert_eq!(decode_entities("Rock & Roll; again"), "Rock & Roll; again");
+        assert_eq!(decode_entities("&unknown; &#xZZ; &#0; &"), "&unknown; &#xZZ; &#0; &");
+        assert_eq!(decode_entities("a && b;"), "a && b;");
+    }
+
+    #[test]
+    fn test_normalize_title() {
+        let decomposed = "Gris - Seizie\u{300}me Prie\u{300}re";
+        assert_eq!(normalize_title(decomposed), "Gris - Seizième Prière");
+        assert_eq!(normalize_title(decomposed), normalize_title("Gris - Seizième Prière"));
+        assert_eq!(normalize_title("  Weakling&nbsp;&ndash; Dead as Dreams "),
+                   "Weakling\u{a0}– Dead as Dreams");
+    }
+}
@@ -0,0 +1,222 @@
+//! Reddit's json envelopes: every object is a "thing" with a `kind` and its
+//! `data`, lists of things come wrapped in a `Listing`.
+//!
+//! A thread (`/comments/<id>.json`) is an array of two listings, the first
+//! holding the post (`t3`) and the second the top level comments (`t1`).
+
+use std::error::Error;
+use std::fmt;
+
+use serde::de::{self, Deserialize, Deserializer};
+use serde_json::{self, Value};
+
+use post::Post;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Thing {
+    Comment(Comment),
+    Post(Post),
+    Listing(Listing),
+    /// comments that were left out of the response
+    More(More),
+    /// any other kind, e.g. `t2` accounts or `t5` subreddits
+    Other(String),
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Listing {
+    pub children: Vec<Thing>,
+    pub after: Option<String>,
+    pub before: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Comment {
+    pub id: Option<String>,
+    pub author: Option<String>,
+    /// html escaped markdown
+    pub body: Option<String>,
+    pub score: Option<i64>,
+    pub created_utc: Option<f64>,
+    /// `t3_<id>` for top level comments, `t1_<id>` for replies
+    pub parent_id: Option<String>,
+    pub permalink: Option<String>,
+    #[serde(deserialize_with = "replies")]
+    pub replies: Option<Listing>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct More {
+    pub count: Option<u64>,
+    pub children: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct RawThing {
+    kind: String,
+    #[serde(default)]
+    data: Value,
+}
+
+impl<'de> Deserialize<'de> for Thing {
+    fn deserialize<D>(deserializer: D) -> Result<Thing, D::Error>
+        where D: Deserializer<'de>
+    {
+        let RawThing { kind, data } = RawThing::deserialize(deserializer)?;
+        let thing = match kind.as_str() {
+            "t1" => serde_json::from_value(data).map(Thing::Comment),
+            "t3" => serde_json::from_value(data).map(Thing::Post),
+            "Listing" => serde_json::from_value(data).map(Thing::Listing),
+            "more" => serde_json::from_value(data).map(Thing::More),
+            _ => return Ok(Thing::Other(kind)),
+        };
+        thing.map_err(|e| de::Error::custom(format!("in {}: {}", kind, e)))
+    }
+}
+
+/// reddit sends `"replies": ""` for comments without replies
+fn replies<'de, D>(deserializer: D) -> Result<Option<Listing>, D::Error>
+    where D: Deserializer<'de>
+{
+    let value = Value::deserialize(deserializer)?;
+    if !value.is_object() {
+        return Ok(None);
+    }
+    match serde_json::from_value(value).map_err(de::Error::custom)? {
+        Thing::Listing(listing) => Ok(Some(listing)),
+        _ => Ok(None),
+    }
+}
+
+#[derive(Debug)]
+pub enum ThreadError {
+    /// not json, or a field of the wrong type
+    Json(serde_json::Error),
+    /// valid json, but not the listings of a thread
+    NotAThread(String),
+    /// the post listing is empty, e.g. for a removed thread
+    NoPost,
+}
+
+impl fmt::Display for ThreadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ThreadError::Json(ref e) => write!(f, "malformed thread json: {}", e),
+            ThreadError::NotAThread(ref found) => write!(f, "not a thread, found {}", found),
+            ThreadError::NoPost => write!(f, "thread has no post"),
+        }
+    }
+}
+
+impl Error for ThreadError {}
+
+impl From<serde_json::Error> for ThreadError {
+    fn from(e: serde_json::Error) -> ThreadError {
+        ThreadError::Json(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Thread {
+    pub post: Post,
+    /// top level comments, replies are nested inside them
+    pub comments: Vec<Thing>,
+}
+
+/// the post and comments of a `/comments/<id>.json` response
+pub fn parse_thread(json: &str) -> Result<Thread, ThreadError> {
+    let value: Value = serde_json::from_str(json)?;
+    let shape = match value {
+        Value::Array(ref listings) if !listings.is_empty() => None,
+        Value::Array(_) => Some(String::from("an empty array")),
+        Value::Object(ref object) => Some(format!("an object with keys {:?}",
+                                                  object.keys().collect::<Vec<_>>())),
+        ref other => Some(other.to_string()),
+    };
+    if let Some(found) = shape {
+        return Err(ThreadError::NotAThread(found));
+    }
+
+    let mut listings = serde_json::from_value::<Vec<Thing>>(value)?.into_iter();
+    let post = match listings.next() {
+        Some(Thing::Listing(listing)) => listing.children.into_iter()
+            .filter_map(|thing| match thing {
+                Thing::Post(post) => Some(post),
+                _ => None,
+            })
+            .next()
+            .ok_or(ThreadError::NoPost)?,
+        Some(Thing::Other(kind)) => return Err(ThreadError::NotAThread(format!("a {} thing", kind))),
+        Some(_) | None => return Err(ThreadError::NotAThread(String::from("no post listing"))),
+    };
+    let comments = match listings.next() {
+        Some(Thing::Listing(listing)) => listing.children,
+        _ => Vec::new(),
+    };
+
+    Ok(Thread { post, comments })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs::File;
+    use std::io::Read;
+
+    #[test]
+    fn test_parse_thread() {
+        let mut json = String::new();
+        File::open("test_resources/5k0ncr.json").expect("could not open json")
+            .read_to_string(&mut json).unwrap();
+        let thread = parse_thread(&json).expect("could not parse thread");
+
+        assert_eq!(thread.post.id, Some(String::from("5k0ncr")));
+        assert!(!thread.comments.is_empty());
+        assert!(thread.comments.iter().all(|thing| match *thing {
+            Thing::Comment(ref comment) => comment.parent_id == Some(String::from("t3_5k0ncr")),
+            Thing::More(_) => true,
+            _ => false,
+        }));
+    }
+
+    #[test]
+    fn test_deleted_post() {
+        let json = r#"[{"kind": "Listing", "data": {"children": [{"kind": "t3", "data":
+                        {"id": "abc", "url": null, "author": "[deleted]", "title": null}}]}},
+                       {"kind": "Listing", "data": {"children": []}}]"#;
+        let thread = parse_thread(json).expect("a deleted post is still a thread");
+        assert_eq!(thread.post.url, None);
+        assert_eq!(thread.post.author, Some(String::from("[deleted]")));
+    }
+
+    #[test]
+    fn test_replies() {
+        let json = r#"{"kind": "t1", "data": {"id": "c1", "replies": {"kind": "Listing", "data":
+                        {"children": [{"kind": "t1", "data": {"id": "c2", "replies": ""}}]}}}}"#;
+        let comment = match serde_json::from_str::<Thing>(json).unwrap() {
+            Thing::Comment(comment) => comment,
+            other => panic!("not a comment: {:?}", other),
+        };
+        let replies = comment.replies.expect("no replies").children;
+        assert_eq!(replies.len(), 1);
+        match replies[0] {
+            Thing::Comment(ref reply) => assert_eq!(reply.replies, None),
+            ref other => panic!("not a comment: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_thread_errors() {
+        let error = |json: &str| parse_thread(json).expect_err(json).to_string();
+
+        assert!(error("<html>").starts_with("malformed thread json"));
+        assert_eq!(error(r#"{"error": 404}"#), "not a thread, found an object with keys [\"error\"]");
+        assert_eq!(error("[]"), "not a thread, found an empty array");
+        assert_eq!(error(r#"[{"kind": "Listing", "data": {"children": []}}]"#), "thread has no post");
+        assert!(error(r#"[{"kind": "Listing", "data": {"children": [{"kind": "t3", "data": {"score": "many"}}]}}]"#)
+                .contains("in t3: invalid type: string \"many\""));
+    }
+}